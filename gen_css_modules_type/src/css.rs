@@ -0,0 +1,555 @@
+//! Just enough of a CSS tokenizer and parser, following CSS Syntax Level 3,
+//! to find the class names used in selectors.
+
+use std::str::CharIndices;
+
+/// A CSS token. Only the text of identifiers and delimiters is kept, since
+/// that's all selectors need.
+#[derive(Debug, PartialEq)]
+pub enum Token<'a> {
+  Ident(&'a str),
+  Function,
+  AtKeyword,
+  Hash,
+  String,
+  BadString,
+  Url,
+  BadUrl,
+  /// A number, dimension such as `0.5em`, or percentage.
+  Number,
+  Whitespace,
+  Colon,
+  Semicolon,
+  Comma,
+  OpenParen,
+  CloseParen,
+  OpenBracket,
+  CloseBracket,
+  OpenBrace,
+  CloseBrace,
+  Delim(char),
+}
+
+/// Splits a stylesheet into tokens, each with its byte offset. Comments are
+/// skipped.
+pub struct Tokenizer<'a> {
+  css: &'a str,
+  chars: CharIndices<'a>,
+}
+
+impl<'a> Tokenizer<'a> {
+  pub fn new(css: &'a str) -> Tokenizer<'a> {
+    Tokenizer {
+      css,
+      chars: css.char_indices(),
+    }
+  }
+
+  fn offset(&self) -> usize {
+    self.chars.offset()
+  }
+
+  fn peek(&self, n: usize) -> Option<char> {
+    self.chars.clone().nth(n).map(|(_, c)| c)
+  }
+
+  fn bump(&mut self) -> Option<char> {
+    self.chars.next().map(|(_, c)| c)
+  }
+
+  fn eat_while(&mut self, accept: impl Fn(char) -> bool) {
+    while self.peek(0).is_some_and(&accept) {
+      self.bump();
+    }
+  }
+
+  fn skip_comments(&mut self) {
+    while self.peek(0) == Some('/') && self.peek(1) == Some('*') {
+      self.bump();
+      self.bump();
+      loop {
+        match self.bump() {
+          Some('*') if self.peek(0) == Some('/') => {
+            self.bump();
+            break;
+          }
+          Some(_) => {}
+          None => return,
+        }
+      }
+    }
+  }
+
+  /// Consumes the character after a backslash, or a hex escape of up to six
+  /// digits and the whitespace that may end it.
+  fn escape(&mut self) {
+    match self.bump() {
+      Some(c) if c.is_ascii_hexdigit() => {
+        for _ in 0..5 {
+          if !self.peek(0).is_some_and(|c| c.is_ascii_hexdigit()) {
+            break;
+          }
+          self.bump();
+        }
+        if self.peek(0).is_some_and(is_whitespace) {
+          self.bump();
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Consumes a name made of name characters and escapes.
+  fn name(&mut self) {
+    loop {
+      match self.peek(0) {
+        Some(c) if is_name(c) => {
+          self.bump();
+        }
+        Some('\\') if is_escape(self.peek(0), self.peek(1)) => {
+          self.bump();
+          self.escape();
+        }
+        _ => return,
+      }
+    }
+  }
+
+  fn starts_ident(&self) -> bool {
+    starts_ident(self.peek(0), self.peek(1), self.peek(2))
+  }
+
+  fn starts_number(&self) -> bool {
+    match (self.peek(0), self.peek(1), self.peek(2)) {
+      (Some('+'), Some(c), _) | (Some('-'), Some(c), _) if c.is_ascii_digit() => true,
+      (Some('+'), Some('.'), Some(c)) | (Some('-'), Some('.'), Some(c)) => c.is_ascii_digit(),
+      (Some('.'), Some(c), _) => c.is_ascii_digit(),
+      (Some(c), _, _) => c.is_ascii_digit(),
+      _ => false,
+    }
+  }
+
+  fn number(&mut self) -> Token<'a> {
+    if let Some('+') | Some('-') = self.peek(0) {
+      self.bump();
+    }
+    self.eat_while(|c| c.is_ascii_digit());
+    if self.peek(0) == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_digit()) {
+      self.bump();
+      self.eat_while(|c| c.is_ascii_digit());
+    }
+    if let Some('e') | Some('E') = self.peek(0) {
+      let digit_at = match self.peek(1) {
+        Some('+') | Some('-') => 2,
+        _ => 1,
+      };
+      if self.peek(digit_at).is_some_and(|c| c.is_ascii_digit()) {
+        for _ in 0..digit_at {
+          self.bump();
+        }
+        self.eat_while(|c| c.is_ascii_digit());
+      }
+    }
+    if self.starts_ident() {
+      self.name();
+    } else if self.peek(0) == Some('%') {
+      self.bump();
+    }
+    Token::Number
+  }
+
+  fn string(&mut self, quote: char) -> Token<'a> {
+    loop {
+      match self.peek(0) {
+        Some(c) if c == quote => {
+          self.bump();
+          return Token::String;
+        }
+        None => return Token::String,
+        Some('\n') => return Token::BadString,
+        Some('\\') => {
+          self.bump();
+          if self.peek(0) == Some('\n') {
+            self.bump();
+          } else if self.peek(0).is_some() {
+            self.escape();
+          }
+        }
+        Some(_) => {
+          self.bump();
+        }
+      }
+    }
+  }
+
+  /// Consumes the rest of an identifier, which may turn out to be a
+  /// function or an unquoted `url(...)`.
+  fn ident_like(&mut self, start: usize) -> Token<'a> {
+    self.name();
+    let name = &self.css[start..self.offset()];
+    if self.peek(0) != Some('(') {
+      return Token::Ident(name);
+    }
+    self.bump();
+    if !name.eq_ignore_ascii_case("url") {
+      return Token::Function;
+    }
+
+    self.eat_while(is_whitespace);
+    match self.peek(0) {
+      Some('"') | Some('\'') => Token::Function,
+      _ => self.url(),
+    }
+  }
+
+  fn url(&mut self) -> Token<'a> {
+    let mut bad = false;
+    loop {
+      match self.bump() {
+        Some(')') | None => break,
+        Some('\\') if is_escape(Some('\\'), self.peek(0)) => self.escape(),
+        Some(c) if c == '"' || c == '\'' || c == '(' || c == '\\' || is_whitespace(c) => {
+          // Whitespace may only come right before the closing parenthesis.
+          if is_whitespace(c) {
+            self.eat_while(is_whitespace);
+            if let Some(')') | None = self.peek(0) {
+              continue;
+            }
+          }
+          bad = true;
+        }
+        Some(_) => {}
+      }
+    }
+    if bad {
+      Token::BadUrl
+    } else {
+      Token::Url
+    }
+  }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+  type Item = (usize, Token<'a>);
+
+  fn next(&mut self) -> Option<(usize, Token<'a>)> {
+    self.skip_comments();
+    let start = self.offset();
+    let c = self.peek(0)?;
+
+    let token = if is_whitespace(c) {
+      self.eat_while(is_whitespace);
+      Token::Whitespace
+    } else if c == '"' || c == '\'' {
+      self.bump();
+      self.string(c)
+    } else if self.starts_number() {
+      self.number()
+    } else if self.starts_ident() {
+      self.ident_like(start)
+    } else {
+      self.bump();
+      match c {
+        '#' if self.peek(0).is_some_and(is_name) || is_escape(self.peek(0), self.peek(1)) => {
+          self.name();
+          Token::Hash
+        }
+        '@' if self.starts_ident() => {
+          self.name();
+          Token::AtKeyword
+        }
+        ':' => Token::Colon,
+        ';' => Token::Semicolon,
+        ',' => Token::Comma,
+        '(' => Token::OpenParen,
+        ')' => Token::CloseParen,
+        '[' => Token::OpenBracket,
+        ']' => Token::CloseBracket,
+        '{' => Token::OpenBrace,
+        '}' => Token::CloseBrace,
+        c => Token::Delim(c),
+      }
+    };
+    Some((start, token))
+  }
+}
+
+fn is_whitespace(c: char) -> bool {
+  c == ' ' || c == '\t' || c == '\n' || c == '\r' || c == '\x0c'
+}
+
+fn is_name_start(c: char) -> bool {
+  c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name(c: char) -> bool {
+  is_name_start(c) || c.is_ascii_digit() || c == '-'
+}
+
+fn is_escape(first: Option<char>, second: Option<char>) -> bool {
+  first == Some('\\') && second.is_some_and(|c| c != '\n')
+}
+
+fn starts_ident(first: Option<char>, second: Option<char>, third: Option<char>) -> bool {
+  match first {
+    Some('-') => {
+      second.is_some_and(|c| is_name_start(c) || c == '-') || is_escape(second, third)
+    }
+    Some('\\') => is_escape(first, second),
+    Some(c) => is_name_start(c),
+    None => false,
+  }
+}
+
+/// Replaces the escapes in an identifier with the characters they stand
+/// for, so `sm\:flex` becomes `sm:flex` and `\31 0` becomes `10`.
+pub fn unescape(ident: &str) -> String {
+  let mut unescaped = String::with_capacity(ident.len());
+  let mut chars = ident.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      unescaped.push(c);
+      continue;
+    }
+    let mut hex = String::new();
+    while hex.len() < 6 && chars.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+      hex.extend(chars.next());
+    }
+    if hex.is_empty() {
+      unescaped.push(chars.next().unwrap_or('\u{FFFD}'));
+      continue;
+    }
+    if chars.peek().is_some_and(|&c| is_whitespace(c)) {
+      chars.next();
+    }
+    // Escapes of NUL, surrogates and values past U+10FFFF all give U+FFFD.
+    let code = u32::from_str_radix(&hex, 16).unwrap_or(0);
+    unescaped.push(match char::from_u32(code) {
+      Some('\0') | None => '\u{FFFD}',
+      Some(c) => c,
+    });
+  }
+  unescaped
+}
+
+/// Returns each class selector in `css`, dot included, in the order they
+/// first appear and without repeats.
+///
+/// Only the preludes of rules, the part before a `{`, are looked at, so
+/// declarations such as `width: 0.5em` or `background: url(icon.png)` are
+/// never mistaken for selectors. At-rule preludes like `@media (...)` are
+/// skipped too, but the rules inside their blocks aren't, and neither are
+/// nested rules.
+pub fn class_selectors(css: &str) -> Vec<&str> {
+  let tokens: Vec<(usize, Token)> = Tokenizer::new(css).collect();
+  let mut classes: Vec<&str> = Vec::new();
+  let mut prelude = 0;
+  for (i, (_, token)) in tokens.iter().enumerate() {
+    match token {
+      Token::OpenBrace => {
+        for class in selector_classes(css, &tokens[prelude..i]) {
+          if !classes.contains(&class) {
+            classes.push(class);
+          }
+        }
+        prelude = i + 1;
+      }
+      Token::CloseBrace | Token::Semicolon => prelude = i + 1,
+      _ => {}
+    }
+  }
+  classes
+}
+
+/// Finds the `.name` pairs in a rule prelude, outside attribute selectors.
+/// Preludes of at-rules have none.
+fn selector_classes<'a>(css: &'a str, prelude: &[(usize, Token<'a>)]) -> Vec<&'a str> {
+  let first = prelude
+    .iter()
+    .find(|(_, token)| *token != Token::Whitespace);
+  if let Some((_, Token::AtKeyword)) = first {
+    return Vec::new();
+  }
+
+  let mut classes = Vec::new();
+  let mut in_attribute = false;
+  for pair in prelude.windows(2) {
+    match pair {
+      [(_, Token::OpenBracket), _] => in_attribute = true,
+      [(_, Token::CloseBracket), _] => in_attribute = false,
+      [(dot, Token::Delim('.')), (start, Token::Ident(name))] if !in_attribute => {
+        classes.push(&css[*dot..start + name.len()]);
+      }
+      _ => {}
+    }
+  }
+  classes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tokens(css: &str) -> Vec<Token<'_>> {
+    Tokenizer::new(css).map(|(_, token)| token).collect()
+  }
+
+  #[test]
+  fn tokenizes_selectors() {
+    assert_eq!(
+      vec![
+        Token::Delim('.'),
+        Token::Ident("btn-primary"),
+        Token::Colon,
+        Token::Ident("hover"),
+        Token::Whitespace,
+        Token::Delim('>'),
+        Token::Whitespace,
+        Token::Hash,
+        Token::Comma,
+        Token::OpenBracket,
+        Token::Ident("href"),
+        Token::Delim('$'),
+        Token::Delim('='),
+        Token::String,
+        Token::CloseBracket,
+        Token::OpenBrace,
+        Token::CloseBrace,
+      ],
+      tokens(".btn-primary:hover > #main,[href$=\".png\"]{}")
+    );
+  }
+
+  #[test]
+  fn numbers_swallow_their_dots_and_units() {
+    assert_eq!(
+      vec![
+        Token::Number,
+        Token::Whitespace,
+        Token::Number,
+        Token::Whitespace,
+        Token::Number,
+        Token::Whitespace,
+        Token::Number,
+        Token::Whitespace,
+        Token::Number,
+      ],
+      tokens("0.5em .5em -.25rem 12.5% 1e3px")
+    );
+    assert_eq!(
+      vec![Token::Number, Token::Whitespace, Token::Ident("x")],
+      tokens("1e x")
+    );
+  }
+
+  #[test]
+  fn skips_comments() {
+    assert_eq!(
+      vec![Token::Delim('.'), Token::Ident("a"), Token::Whitespace],
+      tokens("/* .b */.a/* .c */ /* unterminated .d")
+    );
+  }
+
+  #[test]
+  fn reads_strings_with_escapes() {
+    assert_eq!(vec![Token::String], tokens(r#""a \" .b""#));
+    assert_eq!(vec![Token::String], tokens("'.b'"));
+    assert_eq!(
+      vec![Token::BadString, Token::Whitespace, Token::Ident("c")],
+      tokens("'.b\nc")
+    );
+  }
+
+  #[test]
+  fn reads_urls() {
+    assert_eq!(vec![Token::Url], tokens("url(images/icon.png)"));
+    assert_eq!(vec![Token::Url], tokens("URL(  a.png  )"));
+    assert_eq!(vec![Token::BadUrl, Token::Delim('.'), Token::Ident("b")], tokens("url(a b).b"));
+    assert_eq!(
+      vec![Token::Function, Token::String, Token::CloseParen],
+      tokens("url(\"a.png\")")
+    );
+  }
+
+  #[test]
+  fn reads_escaped_identifiers() {
+    assert_eq!(
+      vec![Token::Delim('.'), Token::Ident(r"sm\:flex")],
+      tokens(r".sm\:flex")
+    );
+    assert_eq!(
+      vec![Token::Delim('.'), Token::Ident(r"\31 0")],
+      tokens(r".\31 0")
+    );
+  }
+
+  #[test]
+  fn unescapes_identifiers() {
+    assert_eq!("sm:flex", unescape(r"sm\:flex"));
+    assert_eq!("10", unescape(r"\31 0"));
+    assert_eq!("a b", unescape(r"a\ b"));
+    assert_eq!("\u{1F600}x", unescape(r"\1F600x"));
+    assert_eq!("\u{FFFD}", unescape(r"\0"));
+    assert_eq!("\u{FFFD}", unescape(r"\D800"));
+    assert_eq!("plain", unescape("plain"));
+  }
+
+  #[test]
+  fn finds_hyphenated_and_compound_classes() {
+    assert_eq!(
+      vec![".btn", ".btn-primary", ".is-active", ".icon_big", ".-x", ".--y"],
+      class_selectors(".btn.btn-primary.is-active > .icon_big, .-x .--y { color: red; }")
+    );
+  }
+
+  #[test]
+  fn ignores_declarations() {
+    let css = "
+      .card {
+        margin: 0.5em .25em;
+        background: url(images/bg.png) no-repeat, url('x.svg');
+        font: 1.5rem/1.2 \"Helvetica.Neue\";
+        transition: opacity .3s;
+      }
+    ";
+    assert_eq!(vec![".card"], class_selectors(css));
+  }
+
+  #[test]
+  fn ignores_comments_and_strings() {
+    let css = "
+      /* .commented { } */
+      .real::before { content: \".fake { }\"; }
+      a[href$='.pdf'] { }
+    ";
+    assert_eq!(vec![".real"], class_selectors(css));
+  }
+
+  #[test]
+  fn looks_inside_at_rules_and_nesting() {
+    let css = "
+      @import url(theme.css);
+      @media (min-width: 0.5em) and (max-width: 40.5em) {
+        .narrow { width: 100%; }
+      }
+      @keyframes spin { 0% { opacity: 0.5; } 12.5% { opacity: 1; } }
+      .parent {
+        color: red;
+        &.active { color: blue; }
+        .child:not(.hidden) { display: block }
+      }
+    ";
+    assert_eq!(
+      vec![".narrow", ".parent", ".active", ".child", ".hidden"],
+      class_selectors(css)
+    );
+  }
+
+  #[test]
+  fn lists_each_class_once() {
+    assert_eq!(
+      vec![".a", ".b"],
+      class_selectors(".a {} .a:hover, .b {} .b .a {}")
+    );
+  }
+}
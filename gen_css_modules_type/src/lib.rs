@@ -1,11 +1,12 @@
 use notify::{EventFn, RecommendedWatcher, RecursiveMode, Result as NotifyResult, Watcher};
-use regex::Regex;
 use std::fs::File;
 use std::io::prelude::Read;
 use std::io::{self, BufRead, Error, Write};
 use std::path::{Path, PathBuf};
 use unicode_segmentation::UnicodeSegmentation;
 
+mod css;
+
 pub const PATH_TO_WATCH: &str = ".";
 
 pub fn create_watcher(path: &str, handler: Box<dyn EventFn>) -> NotifyResult<RecommendedWatcher> {
@@ -20,39 +21,59 @@ pub fn read_line(line: &mut String) -> Result<(), Error> {
   Ok(())
 }
 
-pub fn handle_on_modify(paths: Vec<PathBuf>) -> () {
+pub fn handle_on_modify(paths: Vec<PathBuf>) {
   if !paths.is_empty() {
     let path = &paths[0];
-    match path.extension() {
-      Some(os_ext) => {
-        if let Some("css") = os_ext.to_str() {
-          let type_defs_filename = create_type_defs_filename(path);
-          let contents = extract_file_contents(path);
-          let type_defs = handle_css_change(&contents);
-          save_type_defs(type_defs, &type_defs_filename);
-        }
+    if let Some(os_ext) = path.extension() {
+      if let Some("css") = os_ext.to_str() {
+        let type_defs_filename = create_type_defs_filename(path);
+        let contents = extract_file_contents(path);
+        let type_defs = handle_css_change(&contents);
+        save_type_defs(type_defs, &type_defs_filename);
       }
-      _ => (),
     }
   } else {
     println!("Unknown path, did nothing");
   }
 }
 
+/// JavaScript reserved words, which can't be the name of an export.
+const RESERVED_WORDS: &[&str] = &[
+  "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete",
+  "do", "else", "enum", "export", "extends", "false", "finally", "for", "function", "if",
+  "implements", "import", "in", "instanceof", "interface", "let", "new", "null", "package",
+  "private", "protected", "public", "return", "static", "super", "switch", "this", "throw", "true",
+  "try", "typeof", "var", "void", "while", "with", "yield",
+];
+
+/// Declares a named export for each class that is a valid identifier, and a
+/// default export with every class, since names like `btn-primary` can only
+/// be reached as `styles['btn-primary']`.
 fn handle_css_change(content: &str) -> String {
-  parse_rules(content)
-    .into_iter()
-    .map(remove_dot)
-    .map(type_defs_of_rules)
-    .fold(String::from(""), create_type_def_file_content)
+  let mut class_names: Vec<String> = Vec::new();
+  for name in parse_rules(content).into_iter().map(remove_dot).map(css::unescape) {
+    if !class_names.contains(&name) {
+      class_names.push(name);
+    }
+  }
+
+  let named_exports = class_names
+    .iter()
+    .filter(|name| is_identifier(name))
+    .map(|name| type_defs_of_rules(name))
+    .fold(String::from(""), create_type_def_file_content);
+  let keys = class_names
+    .iter()
+    .map(|name| format!("  readonly {}: string;", property_key(name)))
+    .fold(String::from(""), create_type_def_file_content);
+  format!(
+    "{}declare const styles: {{\n{}}};\nexport default styles;\n",
+    named_exports, keys
+  )
 }
 
 fn parse_rules(css: &str) -> Vec<&str> {
-  let rule_pattern = Regex::new(r"\.\w+").unwrap();
-  rule_pattern
-    .find_iter(&css)
-    .map(|match_| match_.as_str())
-    .collect()
+  css::class_selectors(css)
 }
 
 fn remove_dot(rule: &str) -> &str {
@@ -69,25 +90,49 @@ fn create_type_def_file_content(content: String, type_def: String) -> String {
   format!("{}{}\n", content, type_def)
 }
 
-fn extract_file_contents(path: &PathBuf) -> String {
+fn is_identifier(name: &str) -> bool {
+  let mut chars = name.chars();
+  let starts_well = match chars.next() {
+    Some(c) => c == '_' || c == '$' || c.is_alphabetic(),
+    None => false,
+  };
+  starts_well
+    && chars.all(|c| c == '_' || c == '$' || c.is_alphanumeric())
+    && !RESERVED_WORDS.contains(&name)
+}
+
+/// Writes a class name as an object type key, quoting it unless it is a
+/// valid identifier.
+fn property_key(name: &str) -> String {
+  if is_identifier(name) {
+    return String::from(name);
+  }
+  let escaped = name
+    .replace('\\', "\\\\")
+    .replace('\'', "\\'")
+    .replace('\n', "\\n");
+  format!("'{}'", escaped)
+}
+
+fn extract_file_contents(path: &Path) -> String {
   let mut retries = 0;
   let mut content = String::new();
   while retries < 1_000 {
     let file = File::open(path);
     if let Ok(mut file_contents) = file {
-      if let Ok(_) = file_contents.read_to_string(&mut content) {
+      if file_contents.read_to_string(&mut content).is_ok() {
         break;
       } else {
-        retries = retries + 1;
+        retries += 1;
       }
     } else {
-      retries = retries + 1;
+      retries += 1;
     }
   }
   content
 }
 
-fn create_type_defs_filename(path: &PathBuf) -> PathBuf {
+fn create_type_defs_filename(path: &Path) -> PathBuf {
   let parent = match path.parent() {
     Some(parent) => parent,
     None => Path::new("unknown"),
@@ -97,25 +142,25 @@ fn create_type_defs_filename(path: &PathBuf) -> PathBuf {
   Path::new(parent).join(name)
 }
 
-fn save_type_defs(content: String, path: &PathBuf) -> () {
+fn save_type_defs(content: String, path: &Path) {
   let mut retries = 0;
   while retries < 1_000 {
     let file = File::create(path);
     if let Ok(mut opened_file) = file {
-      if let Ok(_) = opened_file.write_all(content.as_bytes()) {
+      if opened_file.write_all(content.as_bytes()).is_ok() {
         println!("Saved type def to {}", extract_filename(path));
-        return ();
+        return;
       } else {
-        retries = retries + 1;
+        retries += 1;
       }
     } else {
-      retries = retries + 1;
+      retries += 1;
     }
   }
   println!("Failed to save type def to {}", extract_filename(path));
 }
 
-fn extract_filename(path: &PathBuf) -> String {
+fn extract_filename(path: &Path) -> String {
   let unknown_name = "unknown";
   let name = match path.file_name() {
     Some(name) => match name.to_str() {
@@ -127,3 +172,46 @@ fn extract_filename(path: &PathBuf) -> String {
 
   String::from(name)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn generates_type_defs_for_class_selectors_only() {
+    let css = "
+      .btn-primary { padding: 0.5em; background: url(img/icon.png); }
+      .btn-primary:hover, .title { margin: .25em; }
+    ";
+    assert_eq!(
+      "\
+export const title: string;
+declare const styles: {
+  readonly 'btn-primary': string;
+  readonly title: string;
+};
+export default styles;
+",
+      handle_css_change(css)
+    );
+  }
+
+  #[test]
+  fn quotes_names_that_are_not_identifiers() {
+    let css = r".sm\:flex, .\31 0, .class, .it\'s, .a\62 c, .abc {}";
+    assert_eq!(
+      "\
+export const abc: string;
+declare const styles: {
+  readonly 'sm:flex': string;
+  readonly '10': string;
+  readonly 'class': string;
+  readonly 'it\\'s': string;
+  readonly abc: string;
+};
+export default styles;
+",
+      handle_css_change(css)
+    );
+  }
+}
@@ -0,0 +1,75 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use minigrep::{search, search_fast, Config};
+use std::hint::black_box;
+
+/// Builds about 8 MiB of log-like text in which one line in `every` contains
+/// the word `needle`.
+fn haystack(every: usize) -> String {
+    (0..100_000)
+        .map(|i| {
+            if i % every == 0 {
+                format!(
+                    "{:>6} WARN worker {} dropped a needle after {}ms\n",
+                    i,
+                    i % 64,
+                    i % 997
+                )
+            } else {
+                format!(
+                    "{:>6} INFO worker {} finished request {} in {}ms\n",
+                    i,
+                    i % 64,
+                    i * 31,
+                    i % 997
+                )
+            }
+        })
+        .collect::<String>()
+        .repeat(2)
+}
+
+fn literal_search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("literal");
+    for (name, every) in [("rare", 10_000), ("common", 10), ("every line", 1)] {
+        let contents = haystack(every);
+        group.throughput(Throughput::Bytes(contents.len() as u64));
+        group.bench_with_input(BenchmarkId::new("lines", name), &contents, |b, contents| {
+            b.iter(|| search(black_box("needle"), contents))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("memmem", name),
+            &contents,
+            |b, contents| b.iter(|| search_fast(black_box("needle"), contents)),
+        );
+    }
+    group.finish();
+}
+
+/// Matches 200 fixed strings at once, with and without `-i`. Every pattern
+/// contains an `s` or a `k`, which can match non-ASCII text when folded.
+fn many_literals(c: &mut Criterion) {
+    let contents = haystack(10);
+    let patterns: Vec<String> = (0..200).map(|i| format!("--regexp=task {}s", i)).collect();
+    let mut group = c.benchmark_group("many literals");
+    group.throughput(Throughput::Bytes(contents.len() as u64));
+    for (name, flags) in [("-F", &["-F"][..]), ("-F -i", &["-F", "-i"][..])] {
+        let args = ["minigrep"]
+            .iter()
+            .chain(flags)
+            .map(|arg| arg.to_string())
+            .chain(patterns.iter().cloned());
+        let config = Config::new(args).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                black_box(&contents)
+                    .lines()
+                    .filter(|line| !config.matcher.find_iter(line).is_empty())
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, literal_search, many_literals);
+criterion_main!(benches);
@@ -0,0 +1,555 @@
+use encoding_rs::Encoding;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::settings::{Setting, Settings};
+use crate::{ColorChoice, Config, Matcher};
+
+/// Short flags and the long options they stand for.
+const SHORT_FLAGS: &[(char, &str)] = &[
+    ('i', "ignore-case"),
+    ('s', "case-sensitive"),
+    ('v', "invert-match"),
+    ('n', "line-number"),
+    ('b', "byte-offset"),
+    ('o', "only-matching"),
+    ('A', "after-context"),
+    ('B', "before-context"),
+    ('C', "context"),
+    ('c', "count"),
+    ('l', "files-with-matches"),
+    ('L', "files-without-match"),
+    ('q', "quiet"),
+    ('m', "max-count"),
+    ('g', "glob"),
+    ('t', "type"),
+    ('T', "type-not"),
+    ('j', "threads"),
+    ('e', "regexp"),
+    ('f', "file"),
+    ('w', "word-regexp"),
+    ('x', "line-regexp"),
+    ('U', "multiline"),
+    ('F', "fixed-strings"),
+    ('r', "replace"),
+    ('z', "search-zip"),
+    ('E', "encoding"),
+    ('h', "help"),
+    ('V', "version"),
+];
+
+/// Long options that must be followed by a value, either as `--name=value`
+/// or as the next argument.
+const VALUE_OPTIONS: &[&str] = &[
+    "after-context",
+    "before-context",
+    "context",
+    "glob",
+    "type",
+    "type-not",
+    "threads",
+    "replace",
+    "regexp",
+    "file",
+    "encoding",
+    "fuzzy",
+    "max-count",
+    "profile",
+];
+
+/// Parses command line arguments, not including the program name, on top
+/// of the options in `settings`.
+pub fn parse<I>(args: I, settings: &Settings) -> Result<Config, String>
+where
+    I: Iterator<Item = String>,
+{
+    parse_with_env(args, settings, env::var("CASE_INSENSITIVE").is_ok())
+}
+
+/// Like `parse`, with whether `CASE_INSENSITIVE` is set passed in rather
+/// than read from the environment.
+pub(crate) fn parse_with_env<I>(
+    mut args: I,
+    settings: &Settings,
+    ignore_case: bool,
+) -> Result<Config, String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut options: Vec<Setting> = Vec::new();
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline) = match long.find('=') {
+                Some(index) => (&long[..index], Some(long[index + 1..].to_string())),
+                None => (long, None),
+            };
+            let value = if VALUE_OPTIONS.contains(&name) {
+                match inline.or_else(|| args.next()) {
+                    Some(value) => Some(value),
+                    None => return Err(format!("Option --{} needs a value", name)),
+                }
+            } else {
+                inline
+            };
+            options.push((name.to_string(), value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            for (index, flag) in arg.char_indices().skip(1) {
+                let name = match SHORT_FLAGS.iter().find(|(short, _)| *short == flag) {
+                    Some((_, name)) => *name,
+                    None => return Err(format!("Unknown option '-{}'", flag)),
+                };
+                if !VALUE_OPTIONS.contains(&name) {
+                    options.push((name.to_string(), None));
+                    continue;
+                }
+
+                // The value is either the rest of this argument, as in
+                // `-C3`, or the whole of the next one.
+                let rest = &arg[index + flag.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next()
+                } else {
+                    Some(rest.to_string())
+                };
+                match value {
+                    Some(value) => options.push((name.to_string(), Some(value))),
+                    None => return Err(format!("Option -{} needs a value", flag)),
+                }
+                break;
+            }
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    // The configuration file is applied first so the command line wins.
+    let mut profile = None;
+    let mut no_config = false;
+    options.retain(|(name, value)| match name.as_str() {
+        "profile" => {
+            profile = value.clone();
+            false
+        }
+        "no-config" => {
+            no_config = true;
+            false
+        }
+        _ => true,
+    });
+    let defaults = if no_config {
+        Settings::default().options(profile.as_deref())?
+    } else {
+        settings.options(profile.as_deref())?
+    };
+
+    // CASE_INSENSITIVE only applies if neither the file nor the command line
+    // picks a case, so it goes in before either of them.
+    let mut parser = Parser {
+        ignore_case,
+        ..Parser::default()
+    };
+    for (name, value) in defaults {
+        parser
+            .set(&name, value)
+            .map_err(|err| format!("{} in the config file", err))?;
+    }
+    for (name, value) in options {
+        parser.set(&name, value)?;
+    }
+    parser.finish(positional)
+}
+
+#[derive(Default)]
+struct Parser {
+    config: Config,
+    ignore_case: bool,
+    /// Whether any pattern was given with `-e` or `-f`, in which case no
+    /// positional argument is taken as the query.
+    explicit_patterns: bool,
+}
+
+impl Parser {
+    fn set(&mut self, name: &str, value: Option<String>) -> Result<(), String> {
+        let config = &mut self.config;
+        match (name, value) {
+            ("ignore-case", None) => self.ignore_case = true,
+            ("case-sensitive", None) => self.ignore_case = false,
+            ("invert-match", None) => config.invert_match = true,
+            ("line-number", None) => config.line_number = true,
+            ("byte-offset", None) => config.byte_offset = true,
+            ("column", None) => config.column = true,
+            ("only-matching", None) => config.only_matching = true,
+            ("after-context", Some(value)) => config.after_context = parse_number(name, &value)?,
+            ("before-context", Some(value)) => config.before_context = parse_number(name, &value)?,
+            ("context", Some(value)) => {
+                config.after_context = parse_number(name, &value)?;
+                config.before_context = config.after_context;
+            }
+            ("count", None) => config.count = true,
+            ("files-with-matches", None) => config.files_with_matches = true,
+            ("files-without-match", None) => config.files_without_match = true,
+            ("quiet", None) | ("silent", None) => config.quiet = true,
+            ("max-count", Some(value)) => config.max_count = Some(parse_number(name, &value)?),
+            ("regexp", Some(value)) => {
+                config.patterns.push(value);
+                self.explicit_patterns = true;
+            }
+            ("file", Some(value)) => {
+                let contents = fs::read_to_string(&value)
+                    .map_err(|err| format!("Couldn't read patterns from {}: {}", value, err))?;
+                config.patterns.extend(contents.lines().map(String::from));
+                self.explicit_patterns = true;
+            }
+            ("word-regexp", None) => config.word_regexp = true,
+            ("line-regexp", None) => config.line_regexp = true,
+            ("multiline", None) => config.multiline = true,
+            ("fixed-strings", None) => config.fixed_strings = true,
+            ("regex", None) => config.fixed_strings = false,
+            ("fuzzy", Some(value)) => config.fuzzy = Some(parse_number(name, &value)?),
+            ("hidden", None) => config.hidden = true,
+            ("no-ignore", None) => config.no_ignore = true,
+            ("glob", Some(value)) => config.globs.push(value),
+            ("type", Some(value)) => config.types.push(value),
+            ("type-not", Some(value)) => config.types_not.push(value),
+            ("threads", Some(value)) => config.threads = parse_number(name, &value)?,
+            ("color", value) | ("colour", value) => {
+                config.color = match value.as_deref() {
+                    None | Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    Some(value) => return Err(format!("Invalid value '{}' for --{}", value, name)),
+                }
+            }
+            ("replace", Some(value)) => config.replace = Some(value),
+            ("in-place", suffix) => {
+                config.in_place = Some(suffix.unwrap_or_else(|| String::from(".bak")))
+            }
+            ("search-zip", None) => config.search_zip = true,
+            ("encoding", Some(value)) => match Encoding::for_label(value.as_bytes()) {
+                Some(encoding) => config.encoding = Some(encoding),
+                None => return Err(format!("Unknown encoding '{}'", value)),
+            },
+            ("lossy", None) => config.lossy = true,
+            ("json", None) => config.json = true,
+            ("indexed", None) => config.indexed = true,
+            ("tui", None) => config.tui = true,
+            ("watch", None) => config.watch = true,
+            ("help", None) => config.show_help = true,
+            ("version", None) => config.show_version = true,
+            (name, Some(value)) => return Err(format!("Unknown option '--{}={}'", name, value)),
+            (name, None) => return Err(format!("Unknown option '--{}'", name)),
+        }
+        Ok(())
+    }
+
+    fn finish(self, positional: Vec<String>) -> Result<Config, String> {
+        let mut config = self.config;
+        if config.show_help || config.show_version {
+            return Ok(config);
+        }
+
+        if !self.explicit_patterns && positional.len() >= 2 && positional[..2] == ["index", "build"]
+        {
+            return match &positional[2..] {
+                [] => Ok(Config {
+                    index_build: Some(PathBuf::from(".")),
+                    ..config
+                }),
+                [dir] => Ok(Config {
+                    index_build: Some(PathBuf::from(dir)),
+                    ..config
+                }),
+                _ => Err(String::from("'index build' takes a single directory")),
+            };
+        }
+
+        let mut positional = positional.into_iter();
+        if !self.explicit_patterns {
+            match positional.next() {
+                Some(arg) => config.patterns.push(arg),
+                None => return Err(String::from("Didn't get a query string")),
+            }
+        }
+        config.paths = positional.map(PathBuf::from).collect();
+
+        if config.json && (config.summarizes() || config.quiet) {
+            return Err(String::from(
+                "--json can't be combined with --count, --files-with-matches, --files-without-match or --quiet",
+            ));
+        }
+        if config.files_with_matches && config.files_without_match {
+            return Err(String::from(
+                "--files-with-matches can't be combined with --files-without-match",
+            ));
+        }
+
+        if config.fuzzy.is_some() && (config.word_regexp || config.line_regexp) {
+            return Err(String::from(
+                "--fuzzy can't be combined with --word-regexp or --line-regexp",
+            ));
+        }
+
+        if config.tui {
+            if config.json
+                || config.summarizes()
+                || config.quiet
+                || config.replace.is_some()
+                || config.indexed
+                || config.search_zip
+            {
+                return Err(String::from(
+                    "--tui can't be combined with --json, --count, --files-with-matches, --files-without-match, --quiet, --replace, --indexed or --search-zip",
+                ));
+            }
+            // The interface edits a single query.
+            if config.patterns.len() > 1 {
+                return Err(String::from("--tui takes a single QUERY"));
+            }
+            if config.paths.iter().any(|path| path.as_os_str() == "-") {
+                return Err(String::from("--tui can't search standard input"));
+            }
+        }
+
+        // Like grep, only the matches themselves are printed with -o.
+        if config.only_matching {
+            config.after_context = 0;
+            config.before_context = 0;
+        }
+
+        if config.watch {
+            if config.json
+                || config.summarizes()
+                || config.quiet
+                || config.in_place.is_some()
+                || config.tui
+                || config.multiline
+                || config.search_zip
+            {
+                return Err(String::from(
+                    "--watch can't be combined with --json, --count, --files-with-matches, --files-without-match, --quiet, --in-place, --tui, --multiline or --search-zip",
+                ));
+            }
+            if config.paths.iter().any(|path| path.as_os_str() == "-") {
+                return Err(String::from("--watch can't watch standard input"));
+            }
+        }
+
+        if config.multiline && config.replace.is_some() {
+            return Err(String::from("--multiline can't be combined with --replace"));
+        }
+
+        if config.in_place.is_some() {
+            if config.replace.is_none() {
+                return Err(String::from("--in-place needs --replace"));
+            }
+            if config.paths.is_empty() || config.paths.iter().any(|path| path.as_os_str() == "-") {
+                return Err(String::from("--in-place can't rewrite standard input"));
+            }
+            if config.json || config.summarizes() || config.invert_match || config.search_zip {
+                return Err(String::from(
+                    "--in-place can't be combined with --json, --count, --files-with-matches, --files-without-match, --invert-match or --search-zip",
+                ));
+            }
+        }
+
+        config.case_sensitive = !self.ignore_case;
+        config.matcher = Matcher::new(&config)?;
+
+        Ok(config)
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number '{}' for --{}", value, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, args, TempDir};
+
+    #[test]
+    fn case_insensitive_env_is_only_a_fallback() {
+        let parse = |args: &[&str], settings: &Settings| {
+            parse_with_env(args.iter().map(|arg| arg.to_string()), settings, true).unwrap()
+        };
+        let none = Settings::default();
+        let file = Settings::parse("case-sensitive = true\n").unwrap();
+
+        assert!(!parse(&["rust"], &none).case_sensitive);
+        assert!(parse(&["-s", "rust"], &none).case_sensitive);
+        assert!(parse(&["rust"], &file).case_sensitive);
+        assert!(!parse(&["-i", "rust"], &file).case_sensitive);
+    }
+
+    #[test]
+    fn tui_needs_a_single_query_over_plain_files() {
+        assert!(Config::new(args(&["--tui", "-F", "main"])).is_ok());
+        assert!(Config::new(args(&["--tui", "-e", "main", "-e", "fn"])).is_err());
+        assert!(Config::new(args(&["--tui", "-z", "main"])).is_err());
+    }
+
+    #[test]
+    fn parses_flags_and_positionals() {
+        let config = testing::config(&["-in", "--count", "rust", "a.txt", "src"]);
+
+        assert!(!config.case_sensitive);
+        assert!(config.line_number);
+        assert!(config.count);
+        assert!(!config.invert_match);
+        assert_eq!(vec!["rust"], config.patterns);
+        assert_eq!(
+            vec![PathBuf::from("a.txt"), PathBuf::from("src")],
+            config.paths
+        );
+    }
+
+    #[test]
+    fn double_dash_ends_flags() {
+        let config = testing::config(&["-F", "--", "-v", "poem.txt"]);
+
+        assert_eq!(vec!["-v"], config.patterns);
+        assert!(config.fixed_strings);
+        assert!(!config.invert_match);
+    }
+
+    #[test]
+    fn help_does_not_need_a_query() {
+        assert!(testing::config(&["--help"]).show_help);
+    }
+
+    #[test]
+    fn rejects_unknown_flags_and_missing_query() {
+        assert!(Config::new(args(&["-%", "rust", "poem.txt"])).is_err());
+        assert!(Config::new(args(&["-i"])).is_err());
+    }
+
+    #[test]
+    fn no_paths_means_standard_input() {
+        assert!(testing::config(&["rust"]).paths.is_empty());
+    }
+
+    #[test]
+    fn parses_context_flags() {
+        let config = testing::config(&["-C", "2", "-A1", "rust", "poem.txt"]);
+        assert_eq!((2, 1), (config.before_context, config.after_context));
+
+        let config = testing::config(&["--before-context=3", "rust", "poem.txt"]);
+        assert_eq!((3, 0), (config.before_context, config.after_context));
+
+        assert!(Config::new(args(&["-A", "many", "rust", "poem.txt"])).is_err());
+    }
+
+    #[test]
+    fn parses_color_choice() {
+        let config = testing::config(&["--color=never", "rust"]);
+        assert_eq!(ColorChoice::Never, config.color);
+
+        let config = testing::config(&["--colour", "rust"]);
+        assert_eq!(ColorChoice::Auto, config.color);
+
+        assert!(Config::new(args(&["--color=sometimes", "rust"])).is_err());
+    }
+
+    #[test]
+    fn json_excludes_summary_modes() {
+        assert!(testing::config(&["--json", "rust"]).json);
+        assert!(Config::new(args(&["--json", "-c", "rust"])).is_err());
+    }
+
+    #[test]
+    fn in_place_needs_replace_and_files() {
+        let config = testing::config(&["-r", "x", "--in-place", "y", "a.txt"]);
+        assert_eq!(Some(".bak"), config.in_place.as_deref());
+
+        assert!(Config::new(args(&["--in-place", "y", "a.txt"])).is_err());
+        assert!(Config::new(args(&["-r", "x", "--in-place", "y"])).is_err());
+        assert!(Config::new(args(&["-r", "x", "--in-place", "y", "-"])).is_err());
+    }
+
+    #[test]
+    fn explicit_patterns_leave_positionals_as_paths() {
+        let config = testing::config(&["-e", "rust", "--regexp=safe", "poem.txt"]);
+
+        assert_eq!(vec!["rust", "safe"], config.patterns);
+        assert_eq!(vec![PathBuf::from("poem.txt")], config.paths);
+    }
+
+    #[test]
+    fn reads_patterns_from_a_file() {
+        let dir = TempDir::new("patterns");
+        let path = dir.join("patterns.txt");
+        fs::write(&path, "frog\r\nbog\n").unwrap();
+
+        let config = testing::config(&["-f", path.to_str().unwrap(), "-e", "dreary"]);
+
+        assert_eq!(vec!["frog", "bog", "dreary"], config.patterns);
+        assert!(config.paths.is_empty());
+        assert!(Config::new(args(&["-f", "no-such-patterns.txt"])).is_err());
+    }
+
+    #[test]
+    fn parses_encoding_labels() {
+        let config = testing::config(&["-E", "latin1", "rust"]);
+        assert_eq!(Some(encoding_rs::WINDOWS_1252), config.encoding);
+
+        assert!(Config::new(args(&["--encoding=klingon", "rust"])).is_err());
+    }
+
+    #[test]
+    fn index_build_is_a_subcommand() {
+        let config = testing::config(&["--hidden", "index", "build", "src"]);
+        assert_eq!(Some(PathBuf::from("src")), config.index_build);
+        assert!(config.hidden);
+
+        let config = testing::config(&["index", "build"]);
+        assert_eq!(Some(PathBuf::from(".")), config.index_build);
+
+        let config = testing::config(&["-e", "index", "build"]);
+        assert_eq!(None, config.index_build);
+        assert_eq!(vec![PathBuf::from("build")], config.paths);
+
+        assert!(Config::new(args(&["index", "build", "a", "b"])).is_err());
+        assert!(Config::new(args(&["--tui", "--indexed", "main"])).is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_the_config_file() {
+        let settings = Settings::parse(
+            "ignore-case = true\ncontext = 2\nglob = '!*.min.js'\n\
+             [profile.logs]\nglob = '*.log'\ncolor = 'always'\n",
+        )
+        .unwrap();
+        let parse = |args: &[&str]| {
+            super::parse(args.iter().map(|arg| arg.to_string()), &settings).unwrap()
+        };
+
+        let config = parse(&["-C", "1", "rust"]);
+        assert!(!config.case_sensitive);
+        assert_eq!((1, 1), (config.before_context, config.after_context));
+        assert_eq!(vec!["!*.min.js"], config.globs);
+        assert_eq!(ColorChoice::Auto, config.color);
+
+        let config = parse(&["--profile", "logs", "-s", "--color=never", "rust"]);
+        assert!(config.case_sensitive);
+        assert_eq!(vec!["!*.min.js", "*.log"], config.globs);
+        assert_eq!(ColorChoice::Never, config.color);
+
+        assert!(parse(&["--no-config", "rust"]).globs.is_empty());
+        assert!(super::parse(
+            ["--profile=nope", "rust"].iter().map(|arg| arg.to_string()),
+            &settings
+        )
+        .is_err());
+        assert!(Config::new(args(&["--profile", "logs", "rust"])).is_err());
+    }
+}
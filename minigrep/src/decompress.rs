@@ -0,0 +1,99 @@
+//! Transparent decompression for `-z`. Formats are recognised by their magic
+//! bytes rather than by file extension, so rotated logs without a suffix are
+//! handled too.
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::io::{self, Chain, Cursor, Read};
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+const GZIP: &[u8] = &[0x1f, 0x8b];
+const ZSTD: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const BZIP2: &[u8] = b"BZh";
+
+/// Offset of the `ustar` magic within the first header block of a tar file.
+const TAR_MAGIC_OFFSET: usize = 257;
+pub const TAR_HEADER_LEN: usize = 512;
+
+/// Wraps `reader` in a decoder if it starts with the magic bytes of gzip,
+/// zstd or bzip2 data, and otherwise returns it unchanged.
+pub fn decode<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let (head, reader) = peek(reader, ZSTD.len())?;
+    let decoded: Box<dyn Read + 'a> = if head.starts_with(GZIP) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if head.starts_with(ZSTD) {
+        Box::new(ZstdDecoder::new(reader)?)
+    } else if head.starts_with(BZIP2) {
+        Box::new(MultiBzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    Ok(decoded)
+}
+
+/// A reader that replays bytes already read by `peek` before the rest.
+pub type Peeked<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Reads up to `len` bytes from the start of `reader`, returning them along
+/// with a reader that still yields the whole stream.
+pub fn peek<R: Read>(mut reader: R, len: usize) -> io::Result<(Vec<u8>, Peeked<R>)> {
+    let mut head = Vec::with_capacity(len);
+    (&mut reader).take(len as u64).read_to_end(&mut head)?;
+    Ok((head.clone(), Cursor::new(head).chain(reader)))
+}
+
+/// Whether `head`, the first block of a stream, is a POSIX tar header.
+pub fn is_tar(head: &[u8]) -> bool {
+    head.get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5) == Some(b"ustar")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const TEXT: &str = "Rust:\nsafe, fast, productive.\n";
+
+    fn decoded(bytes: Vec<u8>) -> String {
+        let mut text = String::new();
+        decode(&bytes[..])
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn passes_plain_text_through() {
+        assert_eq!(TEXT, decoded(TEXT.as_bytes().to_vec()));
+        assert_eq!("", decoded(Vec::new()));
+    }
+
+    #[test]
+    fn detects_compression_by_magic_bytes() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(TEXT.as_bytes()).unwrap();
+        assert_eq!(TEXT, decoded(gzip.finish().unwrap()));
+
+        assert_eq!(TEXT, decoded(zstd::encode_all(TEXT.as_bytes(), 0).unwrap()));
+
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bzip2.write_all(TEXT.as_bytes()).unwrap();
+        assert_eq!(TEXT, decoded(bzip2.finish().unwrap()));
+    }
+
+    #[test]
+    fn recognises_tar_headers() {
+        let mut archive = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_ustar();
+        header.set_size(TEXT.len() as u64);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, "notes.txt", TEXT.as_bytes())
+            .unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        assert!(is_tar(&archive[..TAR_HEADER_LEN]));
+        assert!(!is_tar(TEXT.as_bytes()));
+    }
+}
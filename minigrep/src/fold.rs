@@ -0,0 +1,142 @@
+//! Case-insensitive literal matching using Unicode full case folding, so
+//! that e.g. `ß` matches `SS` and `ς`, `σ` and `Σ` all match each other.
+//! Nothing here allocates: characters are folded one at a time as they are
+//! compared.
+
+use std::ops::Range;
+use std::str::Chars;
+
+use table::CASE_FOLDING;
+
+mod table;
+
+/// The characters a single character folds to.
+#[derive(Clone)]
+pub enum Folded {
+    Same(Option<char>),
+    Mapped(Chars<'static>),
+}
+
+impl Iterator for Folded {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        match self {
+            Folded::Same(c) => c.take(),
+            Folded::Mapped(chars) => chars.next(),
+        }
+    }
+}
+
+/// Folds `c` into its case-insensitive form, which may be several characters.
+pub fn fold(c: char) -> Folded {
+    if c.is_ascii() {
+        return Folded::Same(Some(c.to_ascii_lowercase()));
+    }
+    match CASE_FOLDING.binary_search_by_key(&c, |&(from, _)| from) {
+        Ok(index) => Folded::Mapped(CASE_FOLDING[index].1.chars()),
+        Err(_) => Folded::Same(Some(c)),
+    }
+}
+
+/// Locates the first case-insensitive match of `query` in `line`. Matches
+/// always start and end on character boundaries of `line`, so `s` does not
+/// match half of a `ß`.
+pub fn find(query: &str, line: &str) -> Option<Range<usize>> {
+    if query.is_empty() {
+        return Some(0..0);
+    }
+    line.char_indices()
+        .find_map(|(start, _)| match_at(query, &line[start..]).map(|len| start..start + len))
+}
+
+/// Whether `query` matches `text` as a whole, case-insensitively.
+pub fn equal(query: &str, text: &str) -> bool {
+    text.is_empty() && query.is_empty() || match_at(query, text) == Some(text.len())
+}
+
+/// Whether an ASCII-only `query` could match some non-ASCII text, so that
+/// ASCII case insensitivity isn't enough to match it: `k` matches the
+/// Kelvin sign, `ss` matches `ß`, `fi` matches `ﬁ`, and so on. Any other
+/// query is always treated as needing full folding.
+pub fn has_non_ascii_match(query: &str) -> bool {
+    if !query.is_ascii() {
+        return true;
+    }
+    let query = query.to_ascii_lowercase();
+    CASE_FOLDING
+        .iter()
+        .any(|&(from, to)| !from.is_ascii() && to.is_ascii() && query.contains(to))
+}
+
+/// Returns the length in bytes of the prefix of `text` that matches `query`.
+fn match_at(query: &str, text: &str) -> Option<usize> {
+    let mut wanted = query.chars().flat_map(fold).peekable();
+    for (index, c) in text.char_indices() {
+        for folded in fold(c) {
+            if wanted.next() != Some(folded) {
+                return None;
+            }
+        }
+        if wanted.peek().is_none() {
+            return Some(index + c.len_utf8());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted() {
+        assert!(CASE_FOLDING.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn sharp_s_matches_double_s() {
+        assert_eq!(Some(4..11), find("strasse", "Die Straße"));
+        assert_eq!(Some(4..11), find("STRAẞE", "Die strasse"));
+        assert_eq!(None, find("stras", "Straße"));
+    }
+
+    #[test]
+    fn greek_sigmas_are_equivalent() {
+        assert_eq!(Some(0..16), find("οδυσσευς", "ΟΔΥΣΣΕΥΣ"));
+        assert_eq!(Some(7..23), find("ΟΔΥΣΣΕΥΣ", "και οδυσσευς"));
+        assert_eq!(Some(0..2), find("ς", "σ"));
+    }
+
+    #[test]
+    fn turkish_dotless_i_is_distinct() {
+        assert_eq!(None, find("ı", "I"));
+        assert_eq!(None, find("i", "ı"));
+        assert_eq!(Some(0..2), find("i\u{307}", "İ"));
+    }
+
+    #[test]
+    fn ascii_queries_that_match_non_ascii_text() {
+        for query in ["strasse", "KILO", "Office", "ST", "ÖL"] {
+            assert!(has_non_ascii_match(query), "{}", query);
+        }
+        for query in ["rule", "a b", "f-i", ""] {
+            assert!(!has_non_ascii_match(query), "{}", query);
+        }
+    }
+
+    #[test]
+    fn equal_needs_the_whole_text() {
+        assert!(equal("STRASSE", "straße"));
+        assert!(!equal("strass", "straße"));
+        assert!(!equal("straße", "strasse!"));
+        assert!(equal("", ""));
+    }
+
+    #[test]
+    fn matches_ascii_and_empty_queries() {
+        assert_eq!(Some(1..5), find("rUsT", "Trust me."));
+        assert_eq!(Some(0..0), find("", "anything"));
+        assert_eq!(None, find("rust", ""));
+    }
+}
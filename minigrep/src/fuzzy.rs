@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+/// Finds the leftmost substring of `line` within `max_distance` edits of
+/// `query`, returning its byte range and its Levenshtein distance.
+///
+/// Once a close enough substring is found the match is extended for as
+/// long as that keeps lowering the distance, so searching for `hello` in
+/// `hello` gives the whole word rather than stopping at `hell`.
+pub fn find(
+    query: &[char],
+    line: &str,
+    max_distance: usize,
+    ignore_case: bool,
+) -> Option<(Range<usize>, usize)> {
+    let mut best: Option<(Range<usize>, usize)> = None;
+    for (span, distance) in Alignments::new(query, line, ignore_case) {
+        match &best {
+            Some((_, closest)) if distance >= *closest => break,
+            None if distance > max_distance => {}
+            _ => best = Some((span, distance)),
+        }
+    }
+    best
+}
+
+/// Returns the smallest Levenshtein distance between `query` and any
+/// substring of `line`.
+pub fn distance(query: &[char], line: &str, ignore_case: bool) -> usize {
+    Alignments::new(query, line, ignore_case)
+        .map(|(_, distance)| distance)
+        .min()
+        .unwrap_or(query.len())
+}
+
+/// Sellers' variant of the Levenshtein algorithm, which lets a match start
+/// anywhere in the line for free. Yields, for each position in the line,
+/// the substring ending there that is closest to the query.
+struct Alignments<'a> {
+    query: &'a [char],
+    chars: std::str::CharIndices<'a>,
+    ignore_case: bool,
+    /// `costs[i]` is the distance between `query[..i]` and the closest
+    /// substring ending at the current position, which starts at `starts[i]`.
+    costs: Vec<usize>,
+    starts: Vec<usize>,
+    started: bool,
+}
+
+impl<'a> Alignments<'a> {
+    fn new(query: &'a [char], line: &'a str, ignore_case: bool) -> Alignments<'a> {
+        Alignments {
+            query,
+            chars: line.char_indices(),
+            ignore_case,
+            costs: (0..=query.len()).collect(),
+            starts: vec![0; query.len() + 1],
+            started: false,
+        }
+    }
+
+    fn current(&self) -> (Range<usize>, usize) {
+        let last = self.query.len();
+        (self.starts[last]..self.starts[0], self.costs[last])
+    }
+}
+
+impl Iterator for Alignments<'_> {
+    type Item = (Range<usize>, usize);
+
+    fn next(&mut self) -> Option<(Range<usize>, usize)> {
+        if !self.started {
+            self.started = true;
+            return Some(self.current());
+        }
+
+        let (offset, c) = self.chars.next()?;
+        let end = offset + c.len_utf8();
+        let mut diagonal = (self.costs[0], self.starts[0]);
+        self.costs[0] = 0;
+        self.starts[0] = end;
+        for i in 1..=self.query.len() {
+            let above = (self.costs[i], self.starts[i]);
+            let substitute = if same(self.query[i - 1], c, self.ignore_case) {
+                diagonal
+            } else {
+                (diagonal.0 + 1, diagonal.1)
+            };
+            // Ties go to the alignment that starts earliest.
+            let cell = substitute
+                .min((above.0 + 1, above.1))
+                .min((self.costs[i - 1] + 1, self.starts[i - 1]));
+            diagonal = above;
+            self.costs[i] = cell.0;
+            self.starts[i] = cell.1;
+        }
+        Some(self.current())
+    }
+}
+
+fn same(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || ignore_case && a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(query: &str) -> Vec<char> {
+        query.chars().collect()
+    }
+
+    #[test]
+    fn finds_misspellings_within_the_distance() {
+        let query = chars("recieve");
+
+        assert_eq!(Some((3..10, 2)), find(&query, "fn receive()", 2, false));
+        assert_eq!(None, find(&query, "fn receive()", 1, false));
+        assert_eq!(Some((0..7, 0)), find(&query, "recieve", 0, false));
+    }
+
+    #[test]
+    fn extends_matches_while_they_improve() {
+        assert_eq!(
+            Some((0..5, 0)),
+            find(&chars("hello"), "hello world", 1, false)
+        );
+        assert_eq!(
+            Some((6..11, 1)),
+            find(&chars("wrld"), "hello world", 1, false)
+        );
+    }
+
+    #[test]
+    fn spans_stay_on_char_boundaries() {
+        assert_eq!(Some((3..9, 1)), find(&chars("naive"), "un naïve", 1, false));
+        assert_eq!(
+            Some((0..5, 0)),
+            find(&chars("CAFÉ"), "café au lait", 0, true)
+        );
+    }
+
+    #[test]
+    fn distance_is_the_closest_substring() {
+        assert_eq!(1, distance(&chars("wrld"), "hello world", false));
+        assert_eq!(0, distance(&chars("lo w"), "hello world", false));
+        assert_eq!(4, distance(&chars("abcd"), "", false));
+    }
+}
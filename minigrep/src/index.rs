@@ -0,0 +1,467 @@
+//! A trigram index for `--indexed`, written by `minigrep index build DIR`
+//! to `DIR/.minigrep-index`.
+//!
+//! The index records, for every file under the directory, its size and
+//! modification time and the set of three-byte sequences it contains. A
+//! literal query can only match in files that contain every trigram of the
+//! query, so the rest can be skipped without being read. Files that changed
+//! since the index was built, or that it doesn't know about, are always
+//! searched, and rebuilding only rereads those.
+
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::{mapped, walk, Config};
+
+/// Name of the index file within the indexed directory.
+pub const FILE_NAME: &str = ".minigrep-index";
+
+const MAGIC: &[u8] = b"minigrep-index 1\n";
+
+/// A trigram packed into the low 24 bits, after ASCII lowercasing.
+type Trigram = u32;
+
+/// What the index knows about one file.
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    len: u64,
+    modified: (u64, u32),
+    /// Sorted trigrams of the file, or `None` if it has to be searched
+    /// whatever the query, such as a UTF-16 file whose bytes aren't text.
+    trigrams: Option<Vec<Trigram>>,
+}
+
+impl Entry {
+    fn is_fresh(&self, metadata: &fs::Metadata) -> bool {
+        self.len == metadata.len() && self.modified == modified(metadata)
+    }
+
+    fn may_contain(&self, query: &[Vec<Trigram>]) -> bool {
+        match &self.trigrams {
+            Some(trigrams) => query.iter().any(|pattern| {
+                pattern
+                    .iter()
+                    .all(|trigram| trigrams.binary_search(trigram).is_ok())
+            }),
+            None => true,
+        }
+    }
+}
+
+/// The index of one directory, keyed by paths relative to it.
+#[derive(Debug, Default, PartialEq)]
+struct Index {
+    entries: HashMap<String, Entry>,
+}
+
+/// What `build` did, for printing.
+#[derive(Debug, Default, PartialEq)]
+pub struct Built {
+    pub files: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+/// Writes the index for `dir`, walking it with the filters in `config`.
+/// Files whose size and modification time are unchanged since the last
+/// build keep their entries; only new and changed files are read.
+pub fn build(dir: &Path, config: &Config) -> io::Result<Built> {
+    let path = dir.join(FILE_NAME);
+    let mut old = match Index::read(&path) {
+        Ok(index) => index,
+        Err(err)
+            if err.kind() == io::ErrorKind::NotFound
+                || err.kind() == io::ErrorKind::InvalidData =>
+        {
+            Index::default()
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut index = Index::default();
+    let mut built = Built::default();
+    for file in walk::files(&[dir.to_path_buf()], config)?.0 {
+        let name = match file.strip_prefix(dir).ok().and_then(Path::to_str) {
+            Some(name) if name != FILE_NAME => name.to_string(),
+            _ => continue,
+        };
+        let metadata = fs::metadata(&file)?;
+        let entry = match old.entries.remove(&name) {
+            Some(entry) if entry.is_fresh(&metadata) => entry,
+            _ => {
+                built.updated += 1;
+                let contents = fs::read(&file).map_err(|err| {
+                    io::Error::new(err.kind(), format!("{}: {}", file.display(), err))
+                })?;
+                Entry {
+                    len: metadata.len(),
+                    modified: modified(&metadata),
+                    trigrams: if mapped::has_bom(&contents) {
+                        None
+                    } else {
+                        Some(trigrams(&contents))
+                    },
+                }
+            }
+        };
+        index.entries.insert(name, entry);
+    }
+    built.files = index.entries.len();
+    built.removed = old.entries.len();
+
+    // Written alongside and renamed so a search never sees half an index.
+    let partial = dir.join(format!("{}.tmp", FILE_NAME));
+    index.write(&partial)?;
+    fs::rename(&partial, &path)?;
+    Ok(built)
+}
+
+/// Drops the files that the indexes of their directories show can't match,
+/// for `--indexed`. Returns `files` unchanged if the query isn't a set of
+/// literals that can be looked up, and fails if a file isn't beneath an
+/// indexed directory.
+pub fn narrow(config: &Config, files: Vec<PathBuf>) -> io::Result<Vec<PathBuf>> {
+    let query = match query(config) {
+        Some(query) => query,
+        None => return Ok(files),
+    };
+
+    let mut indexes: Vec<(PathBuf, Index)> = Vec::new();
+    let mut narrowed = Vec::new();
+    for file in files {
+        if file == Path::new("-") {
+            narrowed.push(file);
+            continue;
+        }
+        let path = fs::canonicalize(&file)?;
+        let found = indexes.iter().position(|(root, _)| path.starts_with(root));
+        let position = match found {
+            Some(position) => position,
+            None => {
+                let root = path
+                    .ancestors()
+                    .skip(1)
+                    .find(|dir| dir.join(FILE_NAME).is_file())
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!(
+                                "{}: no index found; run 'minigrep index build DIR' first",
+                                file.display()
+                            ),
+                        )
+                    })?
+                    .to_path_buf();
+                let index = Index::read(&root.join(FILE_NAME))?;
+                indexes.push((root, index));
+                indexes.len() - 1
+            }
+        };
+
+        let (root, index) = &indexes[position];
+        let entry = path
+            .strip_prefix(root)
+            .ok()
+            .and_then(Path::to_str)
+            .and_then(|name| index.entries.get(name));
+        let keep = match entry {
+            Some(entry) => !entry.is_fresh(&fs::metadata(&path)?) || entry.may_contain(&query),
+            None => true,
+        };
+        if keep {
+            narrowed.push(file);
+        }
+    }
+    Ok(narrowed)
+}
+
+/// Returns the trigrams that each pattern needs, or `None` if some line
+/// could be selected without containing all of any of them: the patterns
+/// aren't all literals of three or more bytes, the file contents are
+/// transformed before searching, or every file must be reported on.
+fn query(config: &Config) -> Option<Vec<Vec<Trigram>>> {
+    if config.fuzzy.is_some()
+        || config.invert_match
+        || config.count
+        || config.files_without_match
+        || config.search_zip
+        || config.encoding.is_some()
+        || config.patterns.is_empty()
+    {
+        return None;
+    }
+
+    let mut query = Vec::new();
+    for pattern in &config.patterns {
+        let literal = config.fixed_strings || regex::escape(pattern) == *pattern;
+        if !literal || pattern.contains(['\n', '\u{FFFD}']) {
+            return None;
+        }
+        let mut needed = trigrams(pattern.as_bytes());
+        // Case-insensitive matching uses Unicode folding, so non-ASCII text
+        // and the letters that fold to it (the Kelvin sign and long s) may
+        // appear in the file as different bytes.
+        if !config.case_sensitive {
+            needed.retain(|&trigram| {
+                unpack(trigram)
+                    .iter()
+                    .all(|&byte| byte.is_ascii() && byte != b'k' && byte != b's')
+            });
+        }
+        if needed.is_empty() {
+            return None;
+        }
+        query.push(needed);
+    }
+    Some(query)
+}
+
+/// Returns the distinct trigrams in `contents`, sorted, ignoring ASCII case
+/// and any that span a line break.
+fn trigrams(contents: &[u8]) -> Vec<Trigram> {
+    let mut trigrams: Vec<Trigram> = contents
+        .windows(3)
+        .filter(|window| !window.contains(&b'\n'))
+        .map(|window| {
+            let [a, b, c] = [0, 1, 2].map(|i| window[i].to_ascii_lowercase() as u32);
+            a << 16 | b << 8 | c
+        })
+        .collect();
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    trigrams
+}
+
+fn unpack(trigram: Trigram) -> [u8; 3] {
+    [(trigram >> 16) as u8, (trigram >> 8) as u8, trigram as u8]
+}
+
+fn modified(metadata: &fs::Metadata) -> (u64, u32) {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or((0, 0), |since| (since.as_secs(), since.subsec_nanos()))
+}
+
+impl Index {
+    /// Reads an index file: the magic line, then for each file its name,
+    /// size, modification time and trigrams, with every number stored as
+    /// little-endian bytes.
+    fn read(path: &Path) -> io::Result<Index> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: not a minigrep index", path.display()),
+            )
+        };
+        let mut input = bytes.strip_prefix(MAGIC).ok_or_else(invalid)?;
+
+        let mut index = Index::default();
+        for _ in 0..take_u64(&mut input).ok_or_else(invalid)? {
+            let len = take_u64(&mut input).ok_or_else(invalid)? as usize;
+            let name = take(&mut input, len)
+                .and_then(|name| String::from_utf8(name.to_vec()).ok())
+                .ok_or_else(invalid)?;
+            let entry = take_entry(&mut input).ok_or_else(invalid)?;
+            index.entries.insert(name, entry);
+        }
+        if !input.is_empty() {
+            return Err(invalid());
+        }
+        Ok(index)
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for (name, entry) in &self.entries {
+            out.write_all(&(name.len() as u64).to_le_bytes())?;
+            out.write_all(name.as_bytes())?;
+            out.write_all(&entry.len.to_le_bytes())?;
+            out.write_all(&entry.modified.0.to_le_bytes())?;
+            out.write_all(&entry.modified.1.to_le_bytes())?;
+            match &entry.trigrams {
+                Some(trigrams) => {
+                    out.write_all(&(trigrams.len() as u64).to_le_bytes())?;
+                    for trigram in trigrams {
+                        out.write_all(&trigram.to_le_bytes())?;
+                    }
+                }
+                None => out.write_all(&u64::MAX.to_le_bytes())?,
+            }
+        }
+        out.flush()
+    }
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if input.len() < len {
+        return None;
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Some(taken)
+}
+
+fn take_u64(input: &mut &[u8]) -> Option<u64> {
+    take(input, 8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn take_u32(input: &mut &[u8]) -> Option<u32> {
+    take(input, 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn take_entry(input: &mut &[u8]) -> Option<Entry> {
+    let len = take_u64(input)?;
+    let modified = (take_u64(input)?, take_u32(input)?);
+    let trigrams = match take_u64(input)? {
+        u64::MAX => None,
+        count => {
+            let count = usize::try_from(count).ok()?;
+            // Checked up front so a corrupt count can't cause a huge allocation.
+            if input.len() < count.checked_mul(4)? {
+                return None;
+            }
+            Some((0..count).map(|_| take_u32(input)).collect::<Option<_>>()?)
+        }
+    };
+    Some(Entry {
+        len,
+        modified,
+        trigrams,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{config, TempDir};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn trigrams_ignore_ascii_case_and_line_breaks() {
+        let expected: Vec<Trigram> = ["abc", "bcd"]
+            .iter()
+            .map(|trigram| trigrams(trigram.as_bytes())[0])
+            .collect();
+        assert_eq!(expected, trigrams(b"aBcD\nab\nABC"));
+        assert_eq!(b"abc", &unpack(trigrams(b"abc")[0]));
+    }
+
+    #[test]
+    fn only_literal_queries_use_the_index() {
+        assert_eq!(Some(vec![trigrams(b"main")]), query(&config(&["main"])));
+        assert_eq!(
+            Some(vec![trigrams(b"a.b.c")]),
+            query(&config(&["-F", "a.b.c"]))
+        );
+
+        assert_eq!(None, query(&config(&["ma.n"])));
+        assert_eq!(None, query(&config(&["-e", "main", "-e", "fn"])));
+        assert_eq!(None, query(&config(&["-v", "main"])));
+        assert_eq!(None, query(&config(&["-c", "main"])));
+        assert_eq!(None, query(&config(&["--fuzzy", "1", "main"])));
+        assert_eq!(None, query(&config(&["-z", "main"])));
+    }
+
+    #[test]
+    fn case_insensitive_queries_skip_letters_with_unicode_folds() {
+        assert_eq!(
+            Some(vec![trigrams(b"mai")]),
+            query(&config(&["-i", "MAIs"]))
+        );
+        assert_eq!(None, query(&config(&["-i", "kiss"])));
+    }
+
+    #[test]
+    fn index_round_trips_through_a_file() {
+        let dir = TempDir::new("index-file");
+        let mut index = Index::default();
+        for (name, trigrams) in [("a.rs", Some(vec![1, 2, 3])), ("b.txt", None)] {
+            let entry = Entry {
+                len: 42,
+                modified: (1_700_000_000, 5),
+                trigrams,
+            };
+            index.entries.insert(name.to_string(), entry);
+        }
+        let path = dir.join(FILE_NAME);
+        index.write(&path).unwrap();
+
+        assert_eq!(index, Index::read(&path).unwrap());
+
+        fs::write(&path, &fs::read(&path).unwrap()[..40]).unwrap();
+        let err = Index::read(&path).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn narrows_to_files_that_may_match() {
+        let dir = TempDir::new("index-narrow");
+        fs::write(dir.join("lib.rs"), "pub fn search() {}\n").unwrap();
+        fs::write(dir.join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.join("utf16.txt"), b"\xff\xfem\0a\0i\0n\0").unwrap();
+        build(&dir, &Config::default()).unwrap();
+
+        let files = vec![
+            dir.join("lib.rs"),
+            dir.join("main.rs"),
+            dir.join("utf16.txt"),
+        ];
+        let narrowed = narrow(&config(&["main"]), files.clone()).unwrap();
+        assert_eq!(vec![dir.join("main.rs"), dir.join("utf16.txt")], narrowed);
+
+        // A file changed since the build is searched whatever it contains.
+        fs::write(dir.join("lib.rs"), "pub fn search() {} // not main\n").unwrap();
+        let narrowed = narrow(&config(&["main"]), files.clone()).unwrap();
+        assert_eq!(files, narrowed);
+
+        let none = TempDir::new("index-none");
+        let elsewhere = none.join("main.rs");
+        fs::write(&elsewhere, "").unwrap();
+        assert!(narrow(&config(&["main"]), vec![elsewhere]).is_err());
+    }
+
+    #[test]
+    fn rebuilding_only_rereads_changed_files() {
+        let dir = TempDir::new("index-update");
+        fs::write(dir.join("a.txt"), "alpha\n").unwrap();
+        fs::write(dir.join("b.txt"), "beta\n").unwrap();
+        fs::write(dir.join("c.txt"), "gamma\n").unwrap();
+        let built = build(&dir, &Config::default()).unwrap();
+        assert_eq!(
+            Built {
+                files: 3,
+                updated: 3,
+                removed: 0
+            },
+            built
+        );
+
+        let later = SystemTime::now() + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(dir.join("a.txt"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        fs::remove_file(dir.join("c.txt")).unwrap();
+        let built = build(&dir, &Config::default()).unwrap();
+        assert_eq!(
+            Built {
+                files: 2,
+                updated: 1,
+                removed: 1
+            },
+            built
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Output for `--json`: one JSON object per line, each with a `type` and a
+//! `data` payload. A search produces, in order,
+//!
+//! * `begin` when a file starts being searched,
+//! * `match` and `context` for each printed line, with the byte offsets of
+//!   every match in the line under `submatches`,
+//! * `end` with the statistics for the file, and finally
+//! * one `summary` with the totals for the whole run.
+
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::Stats;
+
+pub fn begin<W: Write>(out: &mut W, path: &str) -> io::Result<()> {
+    write(out, "begin", json!({ "path": path }))
+}
+
+/// Writes a `match` or `context` event for one line, including the line as
+/// rewritten by `--replace` if there is one.
+#[allow(clippy::too_many_arguments)]
+pub fn line<W: Write>(
+    out: &mut W,
+    kind: &str,
+    path: &str,
+    number: usize,
+    offset: usize,
+    line: &str,
+    spans: &[Range<usize>],
+    replacement: Option<&str>,
+) -> io::Result<()> {
+    let mut data = line_data(path, number, offset, line, spans);
+    if let Some(replacement) = replacement {
+        data["replacement"] = json!(replacement);
+    }
+    write(out, kind, data)
+}
+
+/// Writes a single `match` event for a `--multiline` match, where `lines`
+/// runs from line `number` to line `end_number`.
+pub fn region<W: Write>(
+    out: &mut W,
+    path: &str,
+    number: usize,
+    end_number: usize,
+    offset: usize,
+    lines: &str,
+    spans: &[Range<usize>],
+) -> io::Result<()> {
+    let mut data = line_data(path, number, offset, lines, spans);
+    data["end_line_number"] = json!(end_number);
+    write(out, "match", data)
+}
+
+fn line_data(
+    path: &str,
+    number: usize,
+    offset: usize,
+    line: &str,
+    spans: &[Range<usize>],
+) -> Value {
+    let submatches: Vec<Value> = spans
+        .iter()
+        .map(|span| json!({ "match": &line[span.clone()], "start": span.start, "end": span.end }))
+        .collect();
+    json!({
+        "path": path,
+        "line": line,
+        "line_number": number,
+        "absolute_offset": offset,
+        "submatches": submatches,
+    })
+}
+
+pub fn end<W: Write>(out: &mut W, path: &str, stats: &Stats, elapsed: Duration) -> io::Result<()> {
+    write(
+        out,
+        "end",
+        json!({
+            "path": path,
+            "stats": {
+                "matched_lines": stats.matched_lines,
+                "matches": stats.matches,
+                "bytes_searched": stats.bytes_searched,
+                "elapsed_secs": elapsed.as_secs_f64(),
+            },
+        }),
+    )
+}
+
+pub fn summary<W: Write>(out: &mut W, stats: &Stats, elapsed: Duration) -> io::Result<()> {
+    write(
+        out,
+        "summary",
+        json!({
+            "stats": {
+                "files_searched": stats.files_searched,
+                "files_with_matches": stats.files_with_matches,
+                "matched_lines": stats.matched_lines,
+                "matches": stats.matches,
+                "bytes_searched": stats.bytes_searched,
+                "elapsed_secs": elapsed.as_secs_f64(),
+            },
+        }),
+    )
+}
+
+fn write<W: Write>(out: &mut W, kind: &str, data: Value) -> io::Result<()> {
+    writeln!(out, "{}", json!({ "type": kind, "data": data }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_event_lists_submatches() {
+        let mut out = Vec::new();
+        line(
+            &mut out,
+            "match",
+            "poem.txt",
+            7,
+            121,
+            "like a frog",
+            std::slice::from_ref(&(7..11)),
+            None,
+        )
+        .unwrap();
+
+        let event: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!("match", event["type"]);
+        assert_eq!(7, event["data"]["line_number"]);
+        assert_eq!(121, event["data"]["absolute_offset"]);
+        assert_eq!(
+            json!([{ "match": "frog", "start": 7, "end": 11 }]),
+            event["data"]["submatches"]
+        );
+    }
+
+    #[test]
+    fn one_event_per_line() {
+        let mut out = Vec::new();
+        begin(&mut out, "a \"quoted\"\nname").unwrap();
+        end(
+            &mut out,
+            "a \"quoted\"\nname",
+            &Stats::default(),
+            Duration::from_millis(5),
+        )
+        .unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(2, text.lines().count());
+        for event in text.lines() {
+            assert!(serde_json::from_str::<Value>(event).is_ok());
+        }
+    }
+}
@@ -1,69 +1,1211 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use memmap2::Mmap;
+use regex::Regex;
+use settings::Settings;
+use std::borrow::Cow;
 use std::env;
 use std::error::Error;
-use std::fs;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::{AddAssign, Range};
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Instant;
 
+pub use matcher::Matcher;
+use printer::Printer;
+pub use searcher::{Searcher, SearcherBuilder, Sink};
+
+mod args;
+mod decompress;
+mod fold;
+mod fuzzy;
+mod index;
+mod json;
+mod mapped;
+mod matcher;
+mod multiline;
+mod pool;
+mod printer;
+mod replace;
+mod searcher;
+mod settings;
+#[cfg(test)]
+mod testing;
+mod tui;
+mod walk;
+mod watch;
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY [PATH...]
+       minigrep [OPTIONS] -e QUERY... [-f FILE] [PATH...]
+       minigrep [OPTIONS] index build [DIR]
+
+Search for QUERY in each PATH. Directories are searched recursively, and
+standard input is searched when PATH is '-' or no PATH is given.
+
+Options:
+  -i, --ignore-case          Match case-insensitively (the default if CASE_INSENSITIVE
+                             is set and no other option picks a case)
+  -s, --case-sensitive       Match case-sensitively, overriding the config file
+  -v, --invert-match         Select lines that do not match
+  -n, --line-number          Prefix each line with its line number
+  -b, --byte-offset          Prefix each line with its byte offset in the file
+      --column               Prefix each line with the column of its first match
+  -o, --only-matching        Print each match on its own line instead of the whole
+                             line; context options are ignored
+  -A, --after-context NUM    Print NUM lines of trailing context
+  -B, --before-context NUM   Print NUM lines of leading context
+  -C, --context NUM          Print NUM lines of leading and trailing context
+  -c, --count                Print only a count of matching lines per file
+  -l, --files-with-matches   Print only the names of files with matches
+  -L, --files-without-match  Print only the names of files without matches
+  -q, --quiet                Print nothing, and stop at the first match
+  -m, --max-count NUM        Stop reading a file after NUM selected lines
+      --hidden               Search hidden files and directories
+      --no-ignore            Don't respect .gitignore, .ignore and similar files
+  -g, --glob GLOB            Only search files matching GLOB; prefix with '!' to exclude
+  -t, --type TYPE            Only search files of TYPE, e.g. 'rust' or 'js'
+  -T, --type-not TYPE        Don't search files of TYPE
+  -r, --replace TEXT         Print lines with each match replaced by TEXT, which
+                             can refer to capture groups as $1 or ${name}
+      --in-place[=SUFFIX]    With --replace, rewrite files instead of printing,
+                             keeping a backup with SUFFIX appended (default .bak)
+      --watch                Keep running and print matching lines as they are added
+                             to the files; searches '.' if no PATH is given
+      --tui                  Browse the results in a full-screen interface where the
+                             query can be edited; searches '.' if no PATH is given
+  -z, --search-zip           Search inside gzip, zstd and bzip2 files and tar archives
+  -E, --encoding ENCODING    Decode files as ENCODING, e.g. 'latin1' or 'utf-16le',
+                             unless they start with a byte order mark
+      --lossy                Replace invalid UTF-8 with U+FFFD instead of skipping the file
+      --json                 Print results as JSON Lines, one event per line
+      --indexed              Skip files that the index shows can't contain a literal
+                             QUERY of three or more bytes; files changed since the
+                             index was built are still searched; searches '.'
+                             if no PATH is given
+  -j, --threads NUM          Search NUM files at once (default: one per CPU)
+  -e, --regexp QUERY         Search for QUERY; may be given more than once
+  -f, --file FILE            Search for each line of FILE
+  -w, --word-regexp          Only match whole words
+  -x, --line-regexp          Only match whole lines
+  -U, --multiline            Let matches span lines, e.g. with '\n' or '(?s).';
+                             ^ and $ still match at the start and end of each line
+  -F, --fixed-strings        Treat QUERY as a literal string
+      --regex                Treat QUERY as a regular expression (default)
+      --fuzzy NUM            Match QUERY as a literal string with up to NUM typos,
+                             listing the closest lines first
+      --color[=WHEN]         Highlight matches: 'auto' (default), 'always' or 'never'
+      --profile NAME         Apply the options in [profile.NAME] of the config file
+      --no-config            Don't read the config file
+  -h, --help                 Print this help and exit
+  -V, --version              Print the version and exit
+
+Defaults are read from ~/.config/minigrep/config.toml (or $MINIGREP_CONFIG),
+whose keys are long option names, e.g. 'ignore-case = true' or
+'glob = [\"*.rs\"]'. Options on the command line override the file.
+
+'minigrep index build DIR' writes a trigram index of the files in DIR (default
+'.') to DIR/.minigrep-index for --indexed, honouring the same filters as a
+search. Running it again only rereads files whose size or modification time
+changed. To search for 'index' in a file called 'build', use -e index.
+
+The exit status is 0 if a line was selected (with -L, if a file was listed),
+1 if not, and 2 if an error occurred.";
+
+/// When to highlight output with ANSI colours.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorChoice {
+    /// Colour only when stdout is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves `Auto` into `Always` or `Never` for the current process.
+    pub fn resolve(self) -> ColorChoice {
+        match self {
+            ColorChoice::Auto
+                if io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none() =>
+            {
+                ColorChoice::Always
+            }
+            ColorChoice::Auto => ColorChoice::Never,
+            choice => choice,
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct Config {
-    pub query: String,
-    pub filename: String,
+    /// Patterns to search for; a line matches if any of them match.
+    pub patterns: Vec<String>,
+    /// Files and directories to search; directories are searched recursively.
+    /// `-`, or an empty list, means standard input.
+    pub paths: Vec<PathBuf>,
     pub case_sensitive: bool,
+    /// Treat the patterns as literal strings rather than regular expressions.
+    pub fixed_strings: bool,
+    /// Only match whole words.
+    pub word_regexp: bool,
+    /// Only match whole lines.
+    pub line_regexp: bool,
+    /// Search each file as a whole so that matches can span lines.
+    pub multiline: bool,
+    /// Match the patterns as literal strings within this Levenshtein distance.
+    pub fuzzy: Option<usize>,
+    /// Compiled form of `patterns`.
+    pub matcher: Matcher,
+    pub invert_match: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    /// Prefix lines with the 1-based byte column of their first match.
+    pub column: bool,
+    /// Print each match on its own line rather than the lines containing them.
+    pub only_matching: bool,
+    /// Lines of trailing context to print after each match.
+    pub after_context: usize,
+    /// Lines of leading context to print before each match.
+    pub before_context: usize,
+    pub count: bool,
+    pub files_with_matches: bool,
+    /// Print only the names of files in which no line was selected.
+    pub files_without_match: bool,
+    /// Print nothing, stopping at the first selected line.
+    pub quiet: bool,
+    /// Stop reading a file after this many selected lines.
+    pub max_count: Option<usize>,
+    /// Search hidden files and directories when walking directories.
+    pub hidden: bool,
+    /// Search files excluded by `.gitignore`, `.ignore` and similar files.
+    pub no_ignore: bool,
+    /// Glob overrides for walked files; a leading `!` excludes matches.
+    pub globs: Vec<String>,
+    /// File types to search, such as `rust`; empty means every type.
+    pub types: Vec<String>,
+    /// File types to skip.
+    pub types_not: Vec<String>,
+    /// Number of files to search concurrently, or 0 to use one per CPU.
+    pub threads: usize,
+    pub color: ColorChoice,
+    /// Template to replace each match with when printing.
+    pub replace: Option<String>,
+    /// Rewrite files with `replace` applied instead of printing, keeping
+    /// a backup named with this suffix.
+    pub in_place: Option<String>,
+    /// Decompress compressed files and search inside tar archives.
+    pub search_zip: bool,
+    /// Encoding to decode files from when they have no byte order mark.
+    /// Files with a UTF-16 byte order mark are always transcoded.
+    pub encoding: Option<&'static Encoding>,
+    /// Replace invalid UTF-8 with U+FFFD rather than skipping the file.
+    pub lossy: bool,
+    /// Browse the results interactively instead of printing them.
+    pub tui: bool,
+    /// Print matching lines as they are appended to the files, until
+    /// interrupted.
+    pub watch: bool,
+    /// Print results as JSON Lines rather than text.
+    pub json: bool,
+    /// Use the trigram index to skip files that can't match.
+    pub indexed: bool,
+    /// Build the trigram index for this directory instead of searching.
+    pub index_build: Option<PathBuf>,
+    pub show_help: bool,
+    pub show_version: bool,
 }
 
 impl Config {
-    pub fn new(mut args: std::env::Args) -> Result<Config, &'static str> {
-        args.next();
+    /// Builds a `Config` from command line arguments. The first item is the
+    /// program name and is skipped, as with `std::env::args()`. The
+    /// configuration file is not read, so any `--profile` is an error; use
+    /// `Config::load` to read it.
+    pub fn new<I>(args: I) -> Result<Config, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        args::parse(args.into_iter().skip(1), &Settings::default())
+    }
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
-        };
-        let filename = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Didn't get a query string"),
+    /// Like `Config::new`, but starts from the defaults and profiles in the
+    /// configuration file, `~/.config/minigrep/config.toml`.
+    pub fn load<I>(args: I) -> Result<Config, String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        args::parse(args.into_iter().skip(1), &Settings::load()?)
+    }
+
+    /// Whether each file is summarised by `--count`, `--files-with-matches`
+    /// or `--files-without-match` instead of printing its lines.
+    pub(crate) fn summarizes(&self) -> bool {
+        self.count || self.files_with_matches || self.files_without_match
+    }
+
+    /// Whether a file can be abandoned once a single line is selected.
+    pub(crate) fn stops_at_first_match(&self) -> bool {
+        self.files_with_matches || self.files_without_match || self.quiet
+    }
+
+    /// Locates every non-empty, non-overlapping match of the patterns in `line`.
+    pub(crate) fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        self.matcher.find_iter(line)
+    }
+}
+
+/// Searches the files described by `config`, printing the results to stdout.
+/// Returns whether a line was selected or, with `--files-without-match`, a
+/// file was listed.
+pub fn run(mut config: Config) -> Result<bool, Box<dyn Error>> {
+    if config.show_help {
+        println!("{}", USAGE);
+        return Ok(true);
+    }
+    if config.show_version {
+        println!("minigrep {}", env!("CARGO_PKG_VERSION"));
+        return Ok(true);
+    }
+
+    if let Some(dir) = &config.index_build {
+        let built = index::build(dir, &config)?;
+        println!(
+            "Indexed {} files in {} ({} read, {} removed)",
+            built.files,
+            dir.display(),
+            built.updated,
+            built.removed
+        );
+        return Ok(true);
+    }
+
+    config.color = if config.json {
+        ColorChoice::Never
+    } else {
+        config.color.resolve()
+    };
+    let started = Instant::now();
+    let stdin = [PathBuf::from(
+        if config.tui || config.indexed || config.watch {
+            "."
+        } else {
+            "-"
+        },
+    )];
+    let paths = if config.paths.is_empty() {
+        &stdin[..]
+    } else {
+        &config.paths[..]
+    };
+    let (mut files, unreadable) = walk::files(paths, &config)?;
+    // Decided before narrowing, so the output looks the same either way.
+    let with_filename = files.len() + unreadable > 1 || paths.iter().any(|path| path.is_dir());
+    if config.tui {
+        return Ok(tui::run(&config, &files)?);
+    }
+    if config.watch {
+        return watch::run(&config, paths, with_filename);
+    }
+    if config.indexed {
+        files = index::narrow(&config, files)?;
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut sink = io::sink();
+    let mut out: &mut dyn Write = if config.quiet { &mut sink } else { &mut stdout };
+    let (total, failed) = search_files(&config, &files, with_filename, &mut out)?;
+    let failed = failed + unreadable;
+
+    if config.json {
+        json::summary(&mut out, &total, started.elapsed())?;
+    }
+    if failed > 0 {
+        let files = if failed == 1 { "file" } else { "files" };
+        return Err(format!("{} {} could not be searched", failed, files).into());
+    }
+    if config.files_without_match {
+        Ok(total.files_searched > total.files_with_matches)
+    } else {
+        Ok(total.matched_lines > 0)
+    }
+}
+
+/// Searches `files`, writing the results to `out` in order. A file that
+/// can't be searched is reported on stderr and doesn't stop the others.
+/// Returns the totals for the files that were searched and the number that
+/// failed; only errors writing to `out` are returned as errors.
+fn search_files<W: Write>(
+    config: &Config,
+    files: &[PathBuf],
+    with_filename: bool,
+    mut out: W,
+) -> io::Result<(Stats, usize)> {
+    let mut total = Stats::default();
+    let mut failed = 0;
+    if files.len() == 1 {
+        let mut out = Output {
+            inner: &mut out,
+            failed: false,
         };
+        match search_file(config, &files[0], with_filename, &mut out) {
+            Ok((_, stats)) => total = stats,
+            Err(err) if out.failed => return Err(err),
+            Err(err) => {
+                eprintln!("minigrep: {}", err);
+                failed += 1;
+            }
+        }
+        return Ok((total, failed));
+    }
+
+    // Each worker buffers the output for a whole file so that files are
+    // printed one after another, in the order they were found.
+    let threads = if config.threads > 0 {
+        config.threads
+    } else {
+        thread::available_parallelism().map_or(1, NonZeroUsize::get)
+    };
+    let has_context = config.before_context > 0 || config.after_context > 0;
+    let mut printed_any = false;
+    // With `--quiet`, files not yet started can be skipped after a match.
+    let found = AtomicBool::new(false);
+    pool::for_each_ordered(
+        files,
+        threads,
+        |file| -> io::Result<_> {
+            let mut buffer = Vec::new();
+            if config.quiet && found.load(Ordering::Relaxed) {
+                return Ok(((false, Stats::default()), buffer));
+            }
+            let searched = search_file(config, file, with_filename, &mut buffer)?;
+            if searched.1.matched_lines > 0 {
+                found.store(true, Ordering::Relaxed);
+            }
+            Ok((searched, buffer))
+        },
+        |result| {
+            let ((printed, stats), buffer) = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    eprintln!("minigrep: {}", err);
+                    failed += 1;
+                    return Ok(());
+                }
+            };
+            if has_context && printed && printed_any && !config.json {
+                printer::group_separator(&mut out, config)?;
+            }
+            printed_any |= printed;
+            total += stats;
+            out.write_all(&buffer)
+        },
+    )?;
+    Ok((total, failed))
+}
 
-        let case_sensitive = env::var("CASE_INSENSITIVE").is_err();
+/// Remembers whether writing to `inner` has failed, so that an error from
+/// searching a file can be told apart from one printing its results.
+struct Output<W> {
+    inner: W,
+    failed: bool,
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf);
+        self.failed |= written.is_err();
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let flushed = self.inner.flush();
+        self.failed |= flushed.is_err();
+        flushed
+    }
+}
+
+/// Totals gathered while searching, reported by `--json`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub files_searched: usize,
+    pub files_with_matches: usize,
+    pub matched_lines: usize,
+    /// Number of individual matches, which may be several per line.
+    pub matches: usize,
+    pub bytes_searched: usize,
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.files_searched += other.files_searched;
+        self.files_with_matches += other.files_with_matches;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+        self.bytes_searched += other.bytes_searched;
+    }
+}
+
+/// Searches a single file, or standard input for `-`, writing the results to
+/// `out`. Returns whether any lines were printed, and the statistics for the
+/// file.
+fn search_file<W: Write>(
+    config: &Config,
+    file: &Path,
+    with_filename: bool,
+    out: W,
+) -> io::Result<(bool, Stats)> {
+    let name = if file == Path::new("-") {
+        String::from("(standard input)")
+    } else {
+        file.display().to_string()
+    };
+    if config.in_place.is_some() {
+        let changed = match replace::rewrite_file(config, file) {
+            Ok(changed) => changed,
+            // Like searching, skip files in other encodings rather than
+            // stopping; rewriting them as UTF-8 would mangle them.
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("minigrep: {}: {} (left unchanged)", name, err);
+                return Ok((false, Stats::default()));
+            }
+            Err(err) => return Err(io::Error::new(err.kind(), format!("{}: {}", name, err))),
+        };
+        let stats = Stats {
+            files_searched: 1,
+            files_with_matches: (changed > 0) as usize,
+            matched_lines: changed,
+            ..Stats::default()
+        };
+        return Ok((false, stats));
+    }
 
-        Ok(Config {
-            query,
-            filename,
-            case_sensitive,
+    if config.search_zip {
+        search_compressed(config, file, &name, with_filename, out)
+    } else if file == Path::new("-") {
+        search_named(config, &name, with_filename, io::stdin().lock(), out)
+    } else if let Some(query) = mapped::literal(config) {
+        File::open(file)
+            .and_then(|file| search_mapped(config, &name, with_filename, file, query, out))
+    } else {
+        File::open(file).and_then(|reader| {
+            search_named(config, &name, with_filename, BufReader::new(reader), out)
         })
     }
+    .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))
+}
+
+/// Searches a file that may be compressed or a tar archive, for `-z`. Each
+/// member of an archive is searched separately and labelled as
+/// `archive:member`.
+fn search_compressed<W: Write>(
+    config: &Config,
+    file: &Path,
+    name: &str,
+    with_filename: bool,
+    mut out: W,
+) -> io::Result<(bool, Stats)> {
+    let reader: Box<dyn Read> = if file == Path::new("-") {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(file)?)
+    };
+    let (head, reader) = decompress::peek(decompress::decode(reader)?, decompress::TAR_HEADER_LEN)?;
+    if !decompress::is_tar(&head) {
+        return search_named(config, name, with_filename, BufReader::new(reader), out);
+    }
+
+    let mut printed = false;
+    let mut total = Stats::default();
+    for entry in tar::Archive::new(reader).entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let member = format!("{}:{}", name, entry.path()?.display());
+        let (member_printed, stats) =
+            search_named(config, &member, true, BufReader::new(entry), &mut out)?;
+        printed |= member_printed;
+        total += stats;
+    }
+    Ok((printed, total))
+}
+
+/// Searches `reader`, labelling its lines with `name`, and prints the
+/// per-file summary if one was asked for.
+fn search_named<R: BufRead, W: Write>(
+    config: &Config,
+    name: &str,
+    with_filename: bool,
+    mut reader: R,
+    out: W,
+) -> io::Result<(bool, Stats)> {
+    // Binary files are recognised by their raw bytes, as the decoder below
+    // hands over very little at first. UTF-16 is full of NUL bytes, though,
+    // so that is left to be decoded.
+    let head = reader.fill_buf()?;
+    let utf16 = Encoding::for_bom(head)
+        .map(|(encoding, _)| encoding)
+        .or(config.encoding)
+        .is_some_and(|encoding| encoding == UTF_16LE || encoding == UTF_16BE);
+    if !utf16 && walk::is_binary(head) {
+        return Ok((false, Stats::default()));
+    }
+
+    // Transcodes UTF-16 and anything named by `--encoding` to UTF-8 and
+    // drops byte order marks; other input is passed through untouched.
+    let reader = BufReader::new(
+        DecodeReaderBytesBuilder::new()
+            .encoding(config.encoding)
+            .bom_override(true)
+            .utf8_passthru(true)
+            .strip_bom(true)
+            .build(reader),
+    );
+
+    let started = Instant::now();
+    let mut printer = Printer::new(config, name, with_filename, out);
+    let searched = if config.multiline {
+        search_contents(config, reader, &mut printer)
+    } else {
+        search_reader(config, reader, &mut printer)
+    };
+    let stats = match searched {
+        Ok(stats) => stats,
+        // A file in some other encoding shouldn't stop the rest of the search.
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+            eprintln!("minigrep: {}: {} (try --encoding or --lossy)", name, err);
+            return Ok((printer.has_printed(), Stats::default()));
+        }
+        Err(err) => return Err(err),
+    };
+
+    finish_file(config, printer, stats, started)
+}
+
+/// Searches a large file for a literal by memory-mapping it and scanning
+/// the whole buffer at once. Small files, and anything the line-by-line
+/// search would have to decode, are handed to `search_named` instead.
+fn search_mapped<W: Write>(
+    config: &Config,
+    name: &str,
+    with_filename: bool,
+    file: File,
+    query: &str,
+    out: W,
+) -> io::Result<(bool, Stats)> {
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() < mapped::MIN_LEN {
+        return search_named(config, name, with_filename, BufReader::new(file), out);
+    }
+
+    // SAFETY: the map is only read, and never outlives `file`. If another
+    // process truncates the file while it is mapped we may be killed by
+    // SIGBUS, which is the same trade-off grep and ripgrep make.
+    let map = unsafe { Mmap::map(&file)? };
+    let head = &map[..map.len().min(mapped::SNIFF_LEN)];
+    if walk::is_binary(head) {
+        return Ok((false, Stats::default()));
+    }
+    let contents = match str::from_utf8(&map) {
+        Ok(contents) if !mapped::has_bom(head) => contents,
+        // Leave byte order marks and invalid UTF-8 to the decoding reader.
+        _ => return search_named(config, name, with_filename, BufReader::new(file), out),
+    };
+
+    let started = Instant::now();
+    let mut printer = Printer::new(config, name, with_filename, out);
+    let stats = mapped::search(config, query, contents, &mut printer)?;
+    finish_file(config, printer, stats, started)
+}
+
+/// Prints whatever follows a file's results and reports whether anything
+/// was printed for it.
+fn finish_file<W: Write>(
+    config: &Config,
+    mut printer: Printer<W>,
+    mut stats: Stats,
+    started: Instant,
+) -> io::Result<(bool, Stats)> {
+    stats.matches = printer.matches();
+    if config.json {
+        printer.end(&stats, started.elapsed())?;
+    } else if config.summarizes() {
+        printer.summary(stats.matched_lines)?;
+    }
+    Ok((printer.has_printed(), stats))
+}
+
+/// Searches `reader` one line at a time, so memory use is bounded by the
+/// longest line rather than the size of the input.
+fn search_reader<R: BufRead, W: Write>(
+    config: &Config,
+    reader: R,
+    printer: &mut Printer<W>,
+) -> io::Result<Stats> {
+    printer.begin()?;
+    searcher(config)
+        .build()
+        .search(&config.matcher, reader, printer)
+}
+
+/// Configures a `Searcher` for the line-by-line search described by
+/// `config`. Context isn't wanted when only counting or listing files.
+pub(crate) fn searcher(config: &Config) -> SearcherBuilder {
+    let (before, after) = if config.summarizes() {
+        (0, 0)
+    } else {
+        (config.before_context, config.after_context)
+    };
+    let mut builder = Searcher::builder();
+    builder
+        .invert_match(config.invert_match)
+        .before_context(before)
+        .after_context(after)
+        .max_count(config.max_count)
+        .lossy(config.lossy);
+    builder
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.filename)?;
+/// Searches all of `reader` at once, for `--multiline`, so that matches can
+/// span lines.
+fn search_contents<R: BufRead, W: Write>(
+    config: &Config,
+    mut reader: R,
+    printer: &mut Printer<W>,
+) -> io::Result<Stats> {
+    printer.begin()?;
 
-    let results = if config.case_sensitive {
-        search(&config.query, &contents)
+    let mut buffer = Vec::new();
+    reader.read_to_end(&mut buffer)?;
+    let contents = if config.lossy {
+        String::from_utf8_lossy(&buffer)
     } else {
-        search_case_insensitive(&config.query, &contents)
+        str::from_utf8(&buffer)
+            .map(Cow::Borrowed)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
     };
+    let mut stats = Stats {
+        files_searched: 1,
+        bytes_searched: buffer.len(),
+        ..Stats::default()
+    };
+
+    let regions = multiline::regions(&contents, &config.find_iter(&contents));
+    let mut regions = regions.iter().peekable();
+    // Each match counts once towards `--max-count`, however many lines it spans.
+    let max_count = config.max_count.unwrap_or(usize::MAX);
+    let mut selected = 0;
+    // The last line of the last selection, after which context may follow.
+    let mut selected_to = 0;
+    for (index, (offset, line)) in lines(&contents).enumerate() {
+        let number = index + 1;
+        if selected >= max_count {
+            // Like the line-by-line search, finish the trailing context.
+            if config.summarizes() || number > selected_to + config.after_context {
+                break;
+            }
+            if number > selected_to {
+                printer.line(number, offset, line, false)?;
+            }
+            continue;
+        }
+        while regions
+            .peek()
+            .is_some_and(|region| region.end_line < number)
+        {
+            regions.next();
+        }
+        let region = regions.peek().filter(|region| region.start_line <= number);
 
-    for line in results {
-        println!("{}", line);
+        if region.is_some() == config.invert_match {
+            if !config.summarizes() {
+                printer.line(number, offset, line, false)?;
+            }
+            continue;
+        }
+        match region {
+            Some(region) if !config.invert_match => {
+                // The rest of the region was printed along with its first line.
+                if region.start_line != number {
+                    continue;
+                }
+                stats.matched_lines += region.end_line - region.start_line + 1;
+                selected_to = region.end_line;
+                if !config.summarizes() {
+                    printer.region(number, offset, region.lines, &region.spans)?;
+                }
+            }
+            _ => {
+                stats.matched_lines += 1;
+                selected_to = number;
+                if !config.summarizes() {
+                    printer.line(number, offset, line, true)?;
+                }
+            }
+        }
+        selected += 1;
+        if config.stops_at_first_match() {
+            break;
+        }
     }
 
-    Ok(())
+    if stats.matched_lines > 0 {
+        stats.files_with_matches = 1;
+    }
+    Ok(stats)
+}
+
+/// A run of whole lines of the searched contents containing one or more
+/// matches, which may span several lines.
+#[derive(Debug, PartialEq)]
+pub struct MultilineMatch<'a> {
+    /// One-based number of the first line of the match.
+    pub start_line: usize,
+    /// One-based number of the last line of the match.
+    pub end_line: usize,
+    /// Byte offset of the start of the first line within the contents.
+    pub byte_offset: usize,
+    /// Byte ranges of the matches within `lines`.
+    pub spans: Vec<Range<usize>>,
+    /// The lines from `start_line` to `end_line`, without the final newline.
+    pub lines: &'a str,
+}
+
+/// A line of the searched contents containing a match.
+#[derive(Debug, PartialEq)]
+pub struct Match<'a> {
+    /// One-based number of the line within the contents.
+    pub line_number: usize,
+    /// Byte offset of the start of the line within the contents.
+    pub byte_offset: usize,
+    /// Byte ranges of every match within `line`, in order. Empty for lines
+    /// found by `search_inverted`, and for matches that are themselves empty.
+    pub spans: Vec<Range<usize>>,
+    pub line: &'a str,
+}
+
+/// Splits `contents` into lines like `str::lines`, pairing each line with the
+/// byte offset at which it starts.
+fn lines(contents: &str) -> impl Iterator<Item = (usize, &str)> {
+    contents.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        Some((start, line.strip_suffix('\r').unwrap_or(line)))
+    })
 }
 
-pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
+/// Selects the lines of `contents` in which `find_at` finds a match, where
+/// `find_at(line, start)` looks for the first match at or after `start`.
+fn search_lines<'a, F>(contents: &'a str, find_at: F) -> Vec<Match<'a>>
+where
+    F: Fn(&str, usize) -> Option<Range<usize>>,
+{
+    lines(contents)
+        .enumerate()
+        .filter(|(_, (_, line))| find_at(line, 0).is_some())
+        .map(|(index, (byte_offset, line))| Match {
+            line_number: index + 1,
+            byte_offset,
+            spans: matcher::find_all(line, |start| find_at(line, start)),
+            line,
+        })
         .collect()
 }
 
-pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.to_lowercase().contains(&query.to_lowercase()))
+/// Adapts a function that finds the first match in a string so that it
+/// searches `line` from `start` onwards.
+fn from<F>(line: &str, start: usize, find: F) -> Option<Range<usize>>
+where
+    F: Fn(&str) -> Option<Range<usize>>,
+{
+    find(&line[start..]).map(|span| span.start + start..span.end + start)
+}
+
+fn find_literal(query: &str, line: &str) -> Option<Range<usize>> {
+    line.find(query).map(|start| start..start + query.len())
+}
+
+pub fn search<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, |line, start| {
+        from(line, start, |rest| find_literal(query, rest))
+    })
+}
+
+/// Finds the lines that don't contain `query`, like `grep -v`.
+pub fn search_inverted<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    lines(contents)
+        .enumerate()
+        .filter(|(_, (_, line))| !line.contains(query))
+        .map(|(index, (byte_offset, line))| Match {
+            line_number: index + 1,
+            byte_offset,
+            spans: Vec::new(),
+            line,
+        })
         .collect()
 }
 
+/// Like `search`, but scans all of `contents` for `query` at once with a
+/// vectorised substring finder and only works out line boundaries around
+/// each hit, which is much faster when matches are rare.
+///
+/// `query` must not contain a line break.
+pub fn search_fast<'a>(query: &'a str, contents: &'a str) -> Vec<Match<'a>> {
+    mapped::matches(query, contents).collect()
+}
+
+pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, |line, start| {
+        from(line, start, |rest| fold::find(query, rest))
+    })
+}
+
+/// Finds the lines containing `query` with at most `max_distance`
+/// insertions, deletions or substitutions, closest first. Lines that are
+/// equally close keep their order.
+pub fn search_fuzzy<'a>(query: &str, contents: &'a str, max_distance: usize) -> Vec<Match<'a>> {
+    let query: Vec<char> = query.chars().collect();
+    let mut matches = search_lines(contents, |line, start| {
+        from(line, start, |rest| {
+            fuzzy::find(&query, rest, max_distance, false).map(|(span, _)| span)
+        })
+    });
+    matches.sort_by_cached_key(|found| fuzzy::distance(&query, found.line, false));
+    matches
+}
+
+/// Finds the matches of `pattern` across the whole of `contents` rather than
+/// one line at a time, so the pattern can match line breaks. Matches that
+/// share a line are reported together.
+pub fn search_multiline<'a>(pattern: &Regex, contents: &'a str) -> Vec<MultilineMatch<'a>> {
+    let spans: Vec<_> = pattern
+        .find_iter(contents)
+        .map(|found| found.range())
+        .filter(|span| !span.is_empty())
+        .collect();
+    multiline::regions(contents, &spans)
+}
+
+pub fn search_regex<'a>(pattern: &Regex, contents: &'a str) -> Vec<Match<'a>> {
+    search_lines(contents, |line, start| {
+        pattern.find_at(line, start).map(|found| found.range())
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{self, args, TempDir};
+    use std::fs;
+
+    #[test]
+    fn byte_offsets_account_for_crlf() {
+        let contents = "Rust:\r\nsafe, fast, productive.\r\nTrust me.";
+
+        assert_eq!(
+            vec![
+                (0, "Rust:"),
+                (7, "safe, fast, productive."),
+                (32, "Trust me.")
+            ],
+            lines(contents).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn case_insensitive_span_covers_original_text() {
+        // The Kelvin sign is three bytes long but lowercases to a plain `k`.
+        let matches = search_case_insensitive("kelvin", "0 \u{212A}elvin, kelvin");
+
+        assert_eq!(vec![2..10, 12..18], matches[0].spans);
+    }
+
+    #[test]
+    fn finds_every_match_in_a_line() {
+        let config = testing::config(&["-F", "-i", "us", "poem.txt"]);
+        assert_eq!(vec![0..2, 7..9], config.find_iter("Us and us"));
+
+        let config = testing::config(&["o*", "poem.txt"]);
+        assert_eq!(vec![1..3, 5..6], config.find_iter("foo bot"));
+    }
+
+    #[test]
+    fn transcodes_other_encodings() {
+        let config = testing::config(&["-E", "latin1", "café"]);
+        let mut out = Vec::new();
+        search_named(&config, "menu", false, &b"th\xe9\ncaf\xe9\n"[..], &mut out).unwrap();
+        assert_eq!("café\n", String::from_utf8(out).unwrap());
+
+        let config = testing::config(&["Rust"]);
+        let mut out = Vec::new();
+        let utf16: Vec<u8> = std::iter::once(0xfeff)
+            .chain("Trust\nRust\n".encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        search_named(&config, "utf16", false, &utf16[..], &mut out).unwrap();
+        assert_eq!("Rust\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn fixed_strings_ignore_case_with_full_folding() {
+        let text = "Die Straße\nSTRASSE\nStraßenbahn\n";
+        for (flags, expected) in [
+            (&["-F", "-i"][..], "Die Straße\nSTRASSE\nStraßenbahn\n"),
+            (&["-F", "-i", "-w"][..], "Die Straße\nSTRASSE\n"),
+            (&["-F", "-i", "-x"][..], "STRASSE\n"),
+        ] {
+            let config = Config::new(args(&[flags, &["strasse"]].concat())).unwrap();
+            let mut out = Vec::new();
+            search_named(&config, "de.txt", false, text.as_bytes(), &mut out).unwrap();
+            assert_eq!(expected, String::from_utf8(out).unwrap(), "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn lossy_mode_replaces_invalid_utf8() {
+        let config = testing::config(&["--lossy", "caf"]);
+        let mut out = Vec::new();
+        search_named(&config, "menu", false, &b"caf\xe9\n"[..], &mut out).unwrap();
+        assert_eq!("caf\u{fffd}\n", String::from_utf8(out).unwrap());
+
+        let config = testing::config(&["caf"]);
+        let mut out = Vec::new();
+        let (_, stats) = search_named(&config, "menu", false, &b"caf\xe9\n"[..], &mut out).unwrap();
+        assert_eq!(Stats::default(), stats);
+    }
+
+    #[test]
+    fn fuzzy_mode_ranks_the_closest_lines_first() {
+        let config = testing::config(&["--fuzzy", "2", "-n", "recieve"]);
+        let mut out = Vec::new();
+        let contents = "fn receive()\nfn recieve()\nfn reply()\n";
+        search_named(&config, "lib.rs", false, contents.as_bytes(), &mut out).unwrap();
+        assert_eq!(
+            "2:fn recieve()\n1:fn receive()\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        assert!(Config::new(args(&["--fuzzy", "1", "-w", "recieve"])).is_err());
+        assert!(Config::new(args(&["--fuzzy", "one", "recieve"])).is_err());
+    }
+
+    #[test]
+    fn large_files_are_searched_through_a_memory_map() {
+        let dir = TempDir::new("mapped");
+        let path = dir.join("app.log");
+        let mut contents = "INFO nothing to see here\n".repeat(50_000);
+        contents.push_str("WARN needle in a haystack\r\n");
+        contents.push_str(&"INFO nothing to see here\n".repeat(50_000));
+        fs::write(&path, &contents).unwrap();
+        assert!(contents.len() as u64 >= mapped::MIN_LEN);
+
+        let config = testing::config(&["-nb", "needle"]);
+        let mut out = Vec::new();
+        let (printed, stats) = search_file(&config, &path, false, &mut out).unwrap();
+        assert!(printed);
+        assert_eq!(1, stats.matched_lines);
+        assert_eq!(contents.len(), stats.bytes_searched);
+        assert_eq!(
+            "50001:1250000:WARN needle in a haystack\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn a_bad_file_does_not_stop_the_search() {
+        let dir = TempDir::new("errors");
+        fs::write(dir.join("a.gz"), b"\x1f\x8bnot really gzip\n").unwrap();
+        fs::write(dir.join("b.txt"), "needle\n").unwrap();
+        fs::write(dir.join("c.txt"), "hay\nneedle\n").unwrap();
+        let files: Vec<PathBuf> = ["a.gz", "b.txt", "c.txt"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+
+        let config = testing::config(&["-z", "-c", "needle"]);
+        let mut out = Vec::new();
+        let (stats, failed) = search_files(&config, &files, false, &mut out).unwrap();
+
+        assert_eq!(1, failed);
+        assert_eq!(2, stats.files_with_matches);
+        assert_eq!("1\n1\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn output_errors_are_not_blamed_on_the_file() {
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let dir = TempDir::new("closed");
+        fs::write(dir.join("a.txt"), "needle\n").unwrap();
+        fs::write(dir.join("b.txt"), "needle\n").unwrap();
+        let config = testing::config(&["needle"]);
+        for files in [
+            vec![dir.join("a.txt")],
+            vec![dir.join("a.txt"), dir.join("b.txt")],
+        ] {
+            let err = search_files(&config, &files, false, Closed).unwrap_err();
+            assert_eq!(io::ErrorKind::BrokenPipe, err.kind());
+        }
+    }
+
+    #[test]
+    fn binary_files_are_skipped() {
+        let dir = TempDir::new("binary");
+        fs::write(dir.join("a.bin"), b"needle\0\n").unwrap();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(b"needle\n").unwrap();
+        fs::write(dir.join("b.gz"), gzip.finish().unwrap()).unwrap();
+        fs::write(dir.join("c.txt"), "needle\n").unwrap();
+        let files: Vec<PathBuf> = ["a.bin", "b.gz", "c.txt"]
+            .iter()
+            .map(|name| dir.join(name))
+            .collect();
+
+        for flags in [&[][..], &["-U"][..]] {
+            let config = testing::config(&[flags, &["needle"]].concat());
+            let mut out = Vec::new();
+            let (stats, failed) = search_files(&config, &files, false, &mut out).unwrap();
+
+            assert_eq!(0, failed, "{:?}", flags);
+            assert_eq!(1, stats.files_with_matches, "{:?}", flags);
+            assert_eq!("needle\n", String::from_utf8(out).unwrap(), "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn in_place_skips_files_that_are_not_utf8() {
+        let dir = TempDir::new("in-place");
+        fs::write(dir.join("a.txt"), b"colour caf\xe9\n").unwrap();
+        fs::write(dir.join("b.txt"), "colour\n").unwrap();
+        let files = vec![dir.join("a.txt"), dir.join("b.txt")];
+
+        let config = testing::config(&["-r", "color", "--in-place", "colour", "."]);
+        let (stats, failed) = search_files(&config, &files, true, io::sink()).unwrap();
+
+        assert_eq!((0, 1), (failed, stats.matched_lines));
+        assert_eq!(b"colour caf\xe9\n", &fs::read(&files[0]).unwrap()[..]);
+        assert_eq!(b"color\n", &fs::read(&files[1]).unwrap()[..]);
+    }
+
+    #[test]
+    fn fast_search_matches_search() {
+        let query = "duct";
+        let contents = "\
+                        Rust:\n\
+                        safe, fast, productive.\n\
+                        Pick three.\n\
+                        Duct tape, productively.";
+
+        assert_eq!(search(query, contents), search_fast(query, contents));
+    }
+
+    #[test]
+    fn max_count_stops_after_selected_lines() {
+        let contents = "a\nb\na\na\nc\n";
+        let output = |flags: &[&str]| {
+            let mut args = flags.to_vec();
+            args.push("a");
+            let config = testing::config(&args);
+            let mut out = Vec::new();
+            let (_, stats) =
+                search_named(&config, "letters", false, contents.as_bytes(), &mut out).unwrap();
+            (String::from_utf8(out).unwrap(), stats.matched_lines)
+        };
+
+        assert_eq!((String::from("1:a\n3:a\n"), 2), output(&["-n", "-m", "2"]));
+        assert_eq!((String::from("a\nb\na\n"), 1), output(&["-m1", "-A2"]));
+        assert_eq!(
+            (String::from("a\nb\na\n"), 1),
+            output(&["-U", "-m1", "-A2"])
+        );
+        assert_eq!((String::from("2\n"), 2), output(&["-c", "--max-count=2"]));
+        assert_eq!((String::new(), 0), output(&["-m", "0"]));
+    }
+
+    #[test]
+    fn only_matching_prints_each_match_with_its_column() {
+        let contents = "a rust, a Rust\nTrust me\nnone\n";
+        let output = |flags: &[&str]| {
+            let config = testing::config(flags);
+            let mut out = Vec::new();
+            search_named(&config, "poem.txt", false, contents.as_bytes(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            "1:3:rust\n1:11:Rust\n2:2:rust\n",
+            output(&["-o", "-n", "--column", "-i", "rust"])
+        );
+        assert_eq!(
+            "3:a rust, a Rust\n2:Trust me\n",
+            output(&["--column", "rust"])
+        );
+        assert_eq!("2:rust\n16:rust\n", output(&["-o", "-b", "-C1", "rust"]));
+        assert_eq!("", output(&["-o", "-v", "rust"]));
+    }
+
+    #[test]
+    fn files_without_match_lists_the_other_files() {
+        let config = testing::config(&["-L", "rust"]);
+        let mut out = Vec::new();
+        search_named(&config, "poem.txt", true, &b"Trust me\n"[..], &mut out).unwrap();
+        search_named(&config, "notes.txt", true, &b"Ferris\n"[..], &mut out).unwrap();
+        assert_eq!("notes.txt\n", String::from_utf8(out).unwrap());
+
+        assert!(Config::new(args(&["-l", "-L", "rust"])).is_err());
+        assert!(Config::new(args(&["--json", "-q", "rust"])).is_err());
+    }
+
+    #[test]
+    fn run_reports_whether_anything_was_selected() {
+        let quiet = |args: &[&str]| run(testing::config(args)).unwrap();
+
+        assert!(quiet(&["-q", "nobody", "poem.txt"]));
+        assert!(!quiet(&["-q", "Ferris", "poem.txt"]));
+        assert!(quiet(&["-q", "-L", "Ferris", "poem.txt"]));
+        assert!(!quiet(&["-q", "-L", "nobody", "poem.txt"]));
+        assert!(run(testing::config(&["-q", "nobody", "no-such-file"])).is_err());
+    }
+
+    #[test]
+    fn multiline_mode_prints_whole_matches() {
+        let config = testing::config(&["-U", "-n", r"fn \w+\(\n[^)]*\)"]);
+        let mut out = Vec::new();
+        let contents = "fn main(\n    args: Args,\n) {\n}\nfn other() {}\n";
+        let (_, stats) =
+            search_named(&config, "main.rs", false, contents.as_bytes(), &mut out).unwrap();
+        assert_eq!(
+            "1:fn main(\n2:    args: Args,\n3:) {\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!(3, stats.matched_lines);
+        assert_eq!(1, stats.matches);
+
+        let config = testing::config(&["-U", "-n", "-m1", "-A1", r"fn \w+\(\n[^)]*\)"]);
+        let mut out = Vec::new();
+        search_named(&config, "main.rs", false, contents.as_bytes(), &mut out).unwrap();
+        assert_eq!(
+            "1:fn main(\n2:    args: Args,\n3:) {\n4-}\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        assert!(Config::new(args(&["-U", "-r", "x", "y"])).is_err());
+    }
 
     #[test]
     fn case_sensitive() {
@@ -74,7 +1216,51 @@ mod tests {
                         Pick three.\n\
                         Duct tape.";
 
-        assert_eq!(vec!["safe, fast, productive."], search(query, contents))
+        let matches = search(query, contents);
+
+        assert_eq!(
+            vec![(2, 6, "safe, fast, productive.")],
+            matches
+                .iter()
+                .map(|m| (m.line_number, m.byte_offset, m.line))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&(15..19)), matches[0].spans.first());
+    }
+
+    #[test]
+    fn inverted() {
+        let query = "duct";
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+
+        assert_eq!(
+            vec![(1, "Rust:"), (3, "Pick three.")],
+            search_inverted(query, contents)
+                .iter()
+                .map(|m| (m.line_number, m.line))
+                .collect::<Vec<_>>()
+        );
+        assert!(search_inverted(query, contents)
+            .iter()
+            .all(|m| m.spans.is_empty()));
+    }
+
+    #[test]
+    fn every_match_in_a_line() {
+        let contents = "rust trusts rust\nnothing";
+
+        assert_eq!(vec![0..4, 6..10, 12..16], search("rust", contents)[0].spans);
+        assert_eq!(
+            vec![0..4, 6..10, 12..16],
+            search_regex(&Regex::new("r?ust").unwrap(), contents)[0].spans
+        );
+        assert_eq!(
+            vec![0..4, 6..10, 12..16],
+            search_case_insensitive("RUST", contents)[0].spans
+        );
     }
 
     #[test]
@@ -86,9 +1272,82 @@ mod tests {
                         Pick three.\n\
                         Trust me.";
 
+        let matches = search_case_insensitive(query, contents);
+
         assert_eq!(
             vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            matches.iter().map(|m| m.line).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![Some(0..4), Some(1..5)],
+            matches
+                .iter()
+                .map(|m| m.spans.first().cloned())
+                .collect::<Vec<_>>()
         )
     }
+
+    #[test]
+    fn regex() {
+        let pattern = Regex::new(r"^(Rust|Pick)\b|[0-9]+").unwrap();
+        let contents = "\
+                        Rust:\n\
+                        safe, fast, productive.\n\
+                        Pick three.\n\
+                        Trust me, 100%.";
+
+        assert_eq!(
+            vec![(1, "Rust:"), (3, "Pick three."), (4, "Trust me, 100%.")],
+            search_regex(&pattern, contents)
+                .iter()
+                .map(|m| (m.line_number, m.line))
+                .collect::<Vec<_>>()
+        )
+    }
+
+    #[test]
+    fn fuzzy() {
+        let query = "prodcutive";
+        let contents = "\
+                        Rust:\n\
+                        safe, fast, productive.\n\
+                        Pick three.\n\
+                        safe, fast, prodcutive.";
+
+        let matches = search_fuzzy(query, contents, 2);
+
+        assert_eq!(
+            vec![
+                (4, "safe, fast, prodcutive."),
+                (2, "safe, fast, productive.")
+            ],
+            matches
+                .iter()
+                .map(|m| (m.line_number, m.line))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&(12..22)), matches[1].spans.first());
+        assert_eq!(1, search_fuzzy(query, contents, 1).len());
+    }
+
+    #[test]
+    fn multiline() {
+        let pattern = Regex::new(r"(?m)^Pick three\.\n\w+").unwrap();
+        let contents = "\
+                        Rust:\n\
+                        safe, fast, productive.\n\
+                        Pick three.\n\
+                        Duct tape.";
+
+        let matches = search_multiline(&pattern, contents);
+
+        assert_eq!(
+            vec![(3, 4, 30, "Pick three.\nDuct tape.")],
+            matches
+                .iter()
+                .map(|m| (m.start_line, m.end_line, m.byte_offset, m.lines))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&(0..16)), matches[0].spans.first());
+    }
 }
@@ -1,17 +1,28 @@
 use std::env;
+use std::io;
 use std::process;
 
 use minigrep::Config;
 
 fn main() {
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
-        eprintln!("Problem parising arguments: {}", err);
-        process::exit(1);
+    let config = Config::load(env::args()).unwrap_or_else(|err| {
+        eprintln!("Problem parsing arguments: {}", err);
+        eprintln!("Try 'minigrep --help' for more information.");
+        process::exit(2);
     });
 
-    if let Err(e) = minigrep::run(config) {
-        eprintln!("Application error, {}", e);
+    // Exit like grep: 0 if anything matched, 1 if nothing did and 2 on errors.
+    match minigrep::run(config) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        // The reader went away, as with `minigrep ... | head`, so stop quietly.
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => {}
+        Err(e) => {
+            eprintln!("Application error, {}", e);
 
-        process::exit(1);
+            process::exit(2);
+        }
     }
 }
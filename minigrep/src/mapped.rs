@@ -0,0 +1,143 @@
+use memchr::memmem::Finder;
+use std::io::{self, Write};
+use std::iter;
+
+use crate::printer::Printer;
+use crate::{Config, Match, Sink, Stats};
+
+/// Files smaller than this are read normally, since mapping them costs more
+/// than it saves.
+pub const MIN_LEN: u64 = 1024 * 1024;
+
+/// How much of a mapped file to check for NUL bytes, matching what the
+/// line-by-line search sees in its first buffer.
+pub const SNIFF_LEN: usize = 8 * 1024;
+
+/// Returns the query if `config` can be searched by scanning the whole
+/// buffer for a single literal: one case-sensitive pattern with no regex
+/// metacharacters, and no options that need every line looked at.
+pub fn literal(config: &Config) -> Option<&str> {
+    let pattern = match &config.patterns[..] {
+        [pattern] => pattern,
+        _ => return None,
+    };
+    let plain = config.case_sensitive
+        && !config.word_regexp
+        && !config.line_regexp
+        && config.fuzzy.is_none()
+        && !config.multiline
+        && !config.invert_match
+        && config.after_context == 0
+        && config.before_context == 0
+        && config.encoding.is_none();
+    let literal = !pattern.is_empty()
+        && !pattern.contains(['\n', '\r'])
+        && (config.fixed_strings || regex::escape(pattern) == *pattern);
+    if plain && literal {
+        Some(pattern)
+    } else {
+        None
+    }
+}
+
+/// Reports whether `head` starts with a UTF-8 or UTF-16 byte order mark.
+pub fn has_bom(head: &[u8]) -> bool {
+    [&b"\xef\xbb\xbf"[..], b"\xff\xfe", b"\xfe\xff"]
+        .iter()
+        .any(|bom| head.starts_with(bom))
+}
+
+/// Finds the lines of `contents` containing `query` by searching the whole
+/// buffer with a vectorised substring finder, and only then looking for the
+/// line boundaries around each hit.
+pub fn matches<'a>(query: &'a str, contents: &'a str) -> impl Iterator<Item = Match<'a>> + 'a {
+    let finder = Finder::new(query);
+    let bytes = contents.as_bytes();
+    // Both always sit at the start of a line.
+    let mut searched = 0;
+    let mut counted = 0;
+    let mut line_number = 1;
+
+    iter::from_fn(move || {
+        let hit = searched + finder.find(&bytes[searched..])?;
+        let start =
+            memchr::memrchr(b'\n', &bytes[searched..hit]).map_or(searched, |i| searched + i + 1);
+        let end = memchr::memchr(b'\n', &bytes[hit..]).map_or(bytes.len(), |i| hit + i);
+
+        line_number += memchr::memchr_iter(b'\n', &bytes[counted..start]).count();
+        counted = start;
+        searched = (end + 1).min(bytes.len());
+
+        let line = &contents[start..end];
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let spans = finder
+            .find_iter(line.as_bytes())
+            .map(|at| at..at + query.len())
+            .collect();
+        Some(Match {
+            line_number,
+            byte_offset: start,
+            spans,
+            line,
+        })
+    })
+}
+
+/// Prints the lines of a memory-mapped file that contain `query`.
+pub fn search<W: Write>(
+    config: &Config,
+    query: &str,
+    contents: &str,
+    printer: &mut Printer<W>,
+) -> io::Result<Stats> {
+    printer.begin()?;
+    let mut stats = Stats {
+        files_searched: 1,
+        bytes_searched: contents.len(),
+        ..Stats::default()
+    };
+    let max_count = config.max_count.unwrap_or(usize::MAX);
+    for found in matches(query, contents).take(max_count) {
+        stats.matched_lines += 1;
+        if !printer.matched(&found)? {
+            break;
+        }
+    }
+
+    if stats.matched_lines > 0 {
+        stats.files_with_matches = 1;
+    }
+    printer.finish(&stats)?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+
+    #[test]
+    fn only_plain_literals_take_the_fast_path() {
+        assert_eq!(Some("fn main"), literal(&config(&["fn main"])));
+        assert_eq!(Some("a.b"), literal(&config(&["-F", "a.b"])));
+
+        assert_eq!(None, literal(&config(&["a.b"])));
+        assert_eq!(None, literal(&config(&["-i", "main"])));
+        assert_eq!(None, literal(&config(&["-v", "main"])));
+        assert_eq!(None, literal(&config(&["-C", "1", "main"])));
+        assert_eq!(None, literal(&config(&["-e", "main", "-e", "lib"])));
+    }
+
+    #[test]
+    fn agrees_with_line_by_line_search() {
+        let contents = "Rust:\r\nsafe, fast, productive.\nPick three.\n\nTrust me, rust\nrust";
+        for query in &["rust", "Rust", "t", "three.", "nowhere"] {
+            assert_eq!(
+                crate::search(query, contents),
+                matches(query, contents).collect::<Vec<_>>(),
+                "query {:?}",
+                query
+            );
+        }
+    }
+}
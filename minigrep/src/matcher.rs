@@ -0,0 +1,384 @@
+use aho_corasick::{AhoCorasick, Input, MatchKind};
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+use crate::{fold, fuzzy, Config};
+
+/// Finds the patterns of a `Config` within a line.
+///
+/// How it does so is chosen by the constructor and kept private, so that
+/// it can change without breaking programs using the library.
+pub struct Matcher(Kind);
+
+/// The ways a `Matcher` can find its patterns.
+enum Kind {
+    /// Every pattern joined into a single regular expression.
+    Regex(Regex),
+    /// Fixed strings, matched all at once with an Aho–Corasick automaton
+    /// so that the cost doesn't grow with the number of patterns.
+    Literals(AhoCorasick),
+    /// Fixed strings matched case-insensitively with Unicode case folding,
+    /// which the automaton can only do for ASCII, optionally only as whole
+    /// words or whole lines. Lines that are all ASCII are searched with
+    /// `ascii` instead, built from the folded patterns that are still ASCII,
+    /// as folding them one character at a time is much slower.
+    Folded {
+        patterns: Vec<String>,
+        word_regexp: bool,
+        line_regexp: bool,
+        ascii: Box<Matcher>,
+    },
+    /// Fixed strings matched approximately, allowing up to `max_distance`
+    /// insertions, deletions and substitutions.
+    Fuzzy {
+        patterns: Vec<Vec<char>>,
+        max_distance: usize,
+        ignore_case: bool,
+    },
+}
+
+impl Default for Matcher {
+    /// A matcher with no patterns, which never matches.
+    fn default() -> Matcher {
+        Matcher(Kind::Literals(
+            AhoCorasick::new(Vec::<String>::new()).unwrap(),
+        ))
+    }
+}
+
+impl Matcher {
+    /// A matcher for a single regular expression, in the syntax of the
+    /// `regex` crate.
+    pub fn regex(pattern: &str) -> Result<Matcher, String> {
+        Regex::new(pattern)
+            .map(|pattern| Matcher(Kind::Regex(pattern)))
+            .map_err(|err| err.to_string())
+    }
+
+    /// A matcher for any of several fixed strings, preferring the earliest
+    /// of them when more than one matches at the same place.
+    pub fn literals<I, P>(patterns: I) -> Result<Matcher, String>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostFirst)
+            .build(patterns)
+            .map(|automaton| Matcher(Kind::Literals(automaton)))
+            .map_err(|err| err.to_string())
+    }
+
+    /// Builds the matcher for the patterns and matching options in `config`.
+    ///
+    /// Word and line matching are implemented by wrapping the patterns in a
+    /// regular expression, so fixed strings given with `-w` or `-x` are
+    /// escaped and matched that way, unless they need full case folding.
+    pub fn new(config: &Config) -> Result<Matcher, String> {
+        let patterns = &config.patterns;
+        if patterns.is_empty() {
+            return Ok(Matcher::default());
+        }
+
+        if let Some(max_distance) = config.fuzzy {
+            return Ok(Matcher(Kind::Fuzzy {
+                patterns: patterns
+                    .iter()
+                    .map(|pattern| pattern.chars().collect())
+                    .collect(),
+                max_distance,
+                ignore_case: !config.case_sensitive,
+            }));
+        }
+
+        // The regex crate and the automaton only do simple case folding, so
+        // neither would let `strasse` match `Straße`.
+        if config.fixed_strings
+            && !config.case_sensitive
+            && patterns
+                .iter()
+                .any(|pattern| fold::has_non_ascii_match(pattern))
+        {
+            // On ASCII text full folding is the same as ASCII case
+            // insensitivity, once `ß` has become `ss`; a pattern that still
+            // isn't ASCII when folded can't match there at all.
+            let ascii: Vec<String> = patterns
+                .iter()
+                .map(|pattern| pattern.chars().flat_map(fold::fold).collect::<String>())
+                .filter(|pattern| pattern.is_ascii())
+                .collect();
+            return Ok(Matcher(Kind::Folded {
+                patterns: patterns.clone(),
+                word_regexp: config.word_regexp,
+                line_regexp: config.line_regexp,
+                ascii: Box::new(Matcher::unfolded(&ascii, config)?),
+            }));
+        }
+
+        Matcher::unfolded(patterns, config)
+    }
+
+    /// Builds the automaton or regular expression for `patterns`, which is
+    /// all that's needed unless they need full case folding.
+    fn unfolded(patterns: &[String], config: &Config) -> Result<Matcher, String> {
+        if patterns.is_empty() {
+            return Ok(Matcher::default());
+        }
+
+        if config.fixed_strings && !config.word_regexp && !config.line_regexp {
+            return AhoCorasick::builder()
+                .match_kind(MatchKind::LeftmostFirst)
+                .ascii_case_insensitive(!config.case_sensitive)
+                .build(patterns)
+                .map(|automaton| Matcher(Kind::Literals(automaton)))
+                .map_err(|err| err.to_string());
+        }
+
+        let alternatives: Vec<String> = patterns
+            .iter()
+            .map(|pattern| {
+                if config.fixed_strings {
+                    regex::escape(pattern)
+                } else {
+                    format!("(?:{})", pattern)
+                }
+            })
+            .collect();
+        let mut pattern = alternatives.join("|");
+        if config.line_regexp {
+            pattern = format!("^(?:{})$", pattern);
+        } else if config.word_regexp {
+            pattern = format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern);
+        }
+
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!config.case_sensitive)
+            .multi_line(config.multiline)
+            .build()
+            .map(|pattern| Matcher(Kind::Regex(pattern)))
+            .map_err(|err| err.to_string())
+    }
+
+    /// The regular expression, if this matcher uses one, for expanding
+    /// references to capture groups in `--replace`.
+    pub(crate) fn as_regex(&self) -> Option<&Regex> {
+        match &self.0 {
+            Kind::Regex(pattern) => Some(pattern),
+            _ => None,
+        }
+    }
+
+    /// Locates the first match in `line` that starts at or after the byte
+    /// offset `start`.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<Range<usize>> {
+        match &self.0 {
+            Kind::Regex(pattern) => pattern.find_at(line, start).map(|found| found.range()),
+            Kind::Literals(automaton) => automaton
+                .find(Input::new(line).span(start..line.len()))
+                .map(|found| found.range()),
+            Kind::Folded {
+                patterns,
+                word_regexp,
+                line_regexp,
+                ..
+            } if !line[start..].is_ascii() => patterns
+                .iter()
+                .filter_map(|pattern| {
+                    if *line_regexp {
+                        Some(0..line.len()).filter(|_| start == 0 && fold::equal(pattern, line))
+                    } else if *word_regexp {
+                        find_word(pattern, line, start)
+                    } else {
+                        fold::find(pattern, &line[start..])
+                            .map(|span| span.start + start..span.end + start)
+                    }
+                })
+                .min_by_key(|span| span.start),
+            Kind::Folded { ascii, .. } => ascii.find_at(line, start),
+            Kind::Fuzzy {
+                patterns,
+                max_distance,
+                ignore_case,
+            } => patterns
+                .iter()
+                .filter_map(|pattern| {
+                    fuzzy::find(pattern, &line[start..], *max_distance, *ignore_case)
+                })
+                .map(|(span, _)| span)
+                .min_by_key(|span| span.start)
+                .map(|span| span.start + start..span.end + start),
+        }
+    }
+
+    /// Locates every non-empty, non-overlapping match in `line`.
+    pub fn find_iter(&self, line: &str) -> Vec<Range<usize>> {
+        find_all(line, |start| self.find_at(line, start))
+    }
+
+    /// Returns how far the closest match in `line` is from the patterns,
+    /// which is always zero except for fuzzy matching.
+    pub fn distance(&self, line: &str) -> usize {
+        match &self.0 {
+            Kind::Fuzzy {
+                patterns,
+                ignore_case,
+                ..
+            } => patterns
+                .iter()
+                .map(|pattern| fuzzy::distance(pattern, line, *ignore_case))
+                .min()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+/// Locates the first case-insensitive match of `pattern` in `line` at or
+/// after `start` that isn't next to a word character, like the regular
+/// expression used for `-w`.
+fn find_word(pattern: &str, line: &str, mut start: usize) -> Option<Range<usize>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    loop {
+        let span = fold::find(pattern, &line[start..])?;
+        let span = span.start + start..span.end + start;
+        let before = line[..span.start].chars().next_back();
+        let after = line[span.end..].chars().next();
+        if !before.is_some_and(is_word) && !after.is_some_and(is_word) {
+            return Some(span);
+        }
+        start = span.start + line[span.start..].chars().next()?.len_utf8();
+    }
+}
+
+/// Collects every non-empty, non-overlapping match in `line`, given a way
+/// to find the first match at or after a byte offset. Empty matches are
+/// skipped over a whole character at a time.
+pub fn find_all<F>(line: &str, find_at: F) -> Vec<Range<usize>>
+where
+    F: Fn(usize) -> Option<Range<usize>>,
+{
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start <= line.len() {
+        let span = match find_at(start) {
+            Some(span) => span,
+            None => break,
+        };
+        if span.is_empty() {
+            start = span.end + line[span.end..].chars().next().map_or(1, char::len_utf8);
+        } else {
+            start = span.end;
+            spans.push(span);
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+
+    fn matcher(args: &[&str]) -> Matcher {
+        config(args).matcher
+    }
+
+    #[test]
+    fn finds_the_leftmost_of_many_literals() {
+        let matcher = matcher(&["-F", "-e", "frog", "-e", "bog", "-e", "public"]);
+
+        assert!(matches!(matcher.0, Kind::Literals(_)));
+        assert_eq!(Some(4..10), matcher.find_at("How public, like a frog", 0));
+        assert_eq!(Some(19..23), matcher.find_at("How public, like a frog", 10));
+    }
+
+    #[test]
+    fn folds_non_ascii_literals() {
+        let matcher = matcher(&["-F", "-i", "-e", "STRASSE", "-e", "öl"]);
+
+        assert!(matches!(matcher.0, Kind::Folded { .. }));
+        assert_eq!(Some(4..11), matcher.find_at("Die Straße", 0));
+        assert_eq!(Some(0..3), matcher.find_at("ÖL", 0));
+    }
+
+    #[test]
+    fn folds_ascii_literals_that_match_non_ascii_text() {
+        let folded = matcher(&["-F", "-i", "strasse"]);
+        assert!(matches!(folded.0, Kind::Folded { .. }));
+        assert_eq!(Some(4..11), folded.find_at("Die Straße", 0));
+
+        let literals = matcher(&["-F", "-i", "-e", "rule", "-e", "a.b"]);
+        assert!(matches!(literals.0, Kind::Literals(_)));
+    }
+
+    #[test]
+    fn folded_patterns_match_ascii_lines_like_non_ascii_ones() {
+        let folded = matcher(&["-F", "-i", "-e", "öl", "-e", "STRAẞE", "-e", "\u{212a}m"]);
+
+        assert_eq!(Some(4..11), folded.find_at("Die STRASSE", 0));
+        assert_eq!(Some(15..17), folded.find_at("Die STRASSE, 5 km", 5));
+        assert_eq!(None, folded.find_at("Ol", 0));
+        assert_eq!(Some(0..3), folded.find_at("Öl", 0));
+
+        let words = matcher(&["-F", "-i", "-w", "STRAẞE"]);
+        assert_eq!(Some(5..12), words.find_at("Die, strasse", 0));
+        assert_eq!(None, words.find_at("Hauptstrasse", 0));
+    }
+
+    #[test]
+    fn folded_word_and_line_matching() {
+        let words = matcher(&["-F", "-i", "-w", "strasse"]);
+        assert_eq!(Some(13..20), words.find_at("Straßenbahn Straße", 0));
+        assert_eq!(None, words.find_at("Hauptstraße", 0));
+
+        let lines = matcher(&["-F", "-i", "-x", "STRASSE"]);
+        assert_eq!(Some(0..7), lines.find_at("Straße", 0));
+        assert_eq!(None, lines.find_at("Straße 1", 0));
+        assert_eq!(None, lines.find_at("Straße", 1));
+    }
+
+    #[test]
+    fn word_matching_needs_non_word_neighbours() {
+        let matcher = matcher(&["-w", "-e", "us", "-e", "-v"]);
+
+        assert_eq!(None, matcher.find_at("Trust busts", 0));
+        assert_eq!(Some(8..10), matcher.find_at("pair of us - don't", 0));
+        assert_eq!(Some(4..6), matcher.find_at("run -v now", 0));
+    }
+
+    #[test]
+    fn line_matching_needs_the_whole_line() {
+        let matcher = matcher(&["-x", "-F", "-e", "a.b", "-e", "Pick three."]);
+
+        assert_eq!(None, matcher.find_at("Pick three. Or four.", 0));
+        assert_eq!(Some(0..11), matcher.find_at("Pick three.", 0));
+        assert_eq!(None, matcher.find_at("axb", 0));
+    }
+
+    #[test]
+    fn fuzzy_matching_allows_edits() {
+        let matcher = matcher(&["--fuzzy", "1", "-i", "-e", "conection", "-e", "timout"]);
+
+        assert!(matches!(matcher.0, Kind::Fuzzy { .. }));
+        assert_eq!(Some(5..15), matcher.find_at("Lost connection", 0));
+        assert_eq!(Some(0..7), matcher.find_at("Timeout after 5s", 0));
+        assert_eq!(None, matcher.find_at("Lost contact", 0));
+        assert_eq!(1, matcher.distance("Timeout after 5s"));
+    }
+
+    #[test]
+    fn builds_matchers_without_a_config() {
+        let regex = Matcher::regex("fast|three").unwrap();
+        assert_eq!(Some(6..10), regex.find_at("safe, fast", 0));
+        assert!(Matcher::regex("(unclosed").is_err());
+
+        let literals = Matcher::literals(["frog", "bog"]).unwrap();
+        assert_eq!(vec![2..6, 12..15], literals.find_iter("a frog in a bog"));
+        assert_eq!(None, literals.find_at("a Frog", 0));
+    }
+
+    #[test]
+    fn no_patterns_never_match() {
+        assert_eq!(None, Matcher::default().find_at("anything", 0));
+    }
+}
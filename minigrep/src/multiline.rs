@@ -0,0 +1,100 @@
+use std::ops::Range;
+
+use crate::MultilineMatch;
+
+/// Expands the matches in `contents`, given as sorted, non-overlapping byte
+/// ranges, to the whole lines they touch. Matches that share a line are
+/// reported together, so every line belongs to at most one result.
+pub fn regions<'a>(contents: &'a str, spans: &[Range<usize>]) -> Vec<MultilineMatch<'a>> {
+    let bytes = contents.as_bytes();
+    let mut regions: Vec<MultilineMatch> = Vec::new();
+    // Line number of the byte offset `counted`, which is always a line start.
+    let mut counted = 0;
+    let mut line_number = 1;
+
+    for span in spans {
+        let start = memchr::memrchr(b'\n', &bytes[..span.start]).map_or(0, |i| i + 1);
+        // A match ending in a newline doesn't reach onto the next line.
+        let last = if span.end > span.start {
+            span.end - 1
+        } else {
+            span.end
+        };
+        let end = memchr::memchr(b'\n', &bytes[last..]).map_or(bytes.len(), |i| last + i);
+
+        if let Some(region) = regions.last_mut() {
+            if start <= region.byte_offset + region.lines.len() {
+                let offset = region.byte_offset;
+                region
+                    .spans
+                    .push(span.start - offset..span.end.min(end) - offset);
+                region.end_line += memchr::memchr_iter(b'\n', &bytes[counted..end]).count();
+                region.lines = &contents[region.byte_offset..end];
+                counted = end;
+                line_number = region.end_line;
+                continue;
+            }
+        }
+
+        line_number += memchr::memchr_iter(b'\n', &bytes[counted..start]).count();
+        let first = span.start - start..span.end.min(end) - start;
+        let end_line = line_number + memchr::memchr_iter(b'\n', &bytes[start..end]).count();
+        regions.push(MultilineMatch {
+            start_line: line_number,
+            end_line,
+            byte_offset: start,
+            spans: vec![first],
+            lines: &contents[start..end],
+        });
+        counted = end;
+        line_number = end_line;
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(regions: &[MultilineMatch]) -> Vec<(usize, usize, String)> {
+        regions
+            .iter()
+            .map(|region| (region.start_line, region.end_line, region.lines.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expands_matches_to_whole_lines() {
+        let contents = "fn main(\n    args: Args,\n) {\n}\nfn other() {}\n";
+        let regions = regions(contents, &[3..29, 35..40]);
+
+        assert_eq!(
+            vec![
+                (1, 3, String::from("fn main(\n    args: Args,\n) {")),
+                (5, 5, String::from("fn other() {}")),
+            ],
+            lines(&regions)
+        );
+        assert_eq!(vec![3..28], regions[0].spans);
+        assert_eq!(vec![4..9], regions[1].spans);
+    }
+
+    #[test]
+    fn merges_matches_that_share_a_line() {
+        let contents = "a b\nc d\ne\n";
+        let regions = regions(contents, &[0..1, 2..5, 6..7, 8..9]);
+
+        assert_eq!(
+            vec![(1, 2, String::from("a b\nc d")), (3, 3, String::from("e"))],
+            lines(&regions)
+        );
+        assert_eq!(vec![0..1, 2..5, 6..7], regions[0].spans);
+    }
+
+    #[test]
+    fn trailing_newline_stays_on_its_line() {
+        let regions = regions("one\ntwo\n", std::slice::from_ref(&(0..4)));
+
+        assert_eq!(vec![(1, 1, String::from("one"))], lines(&regions));
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Runs `job` over `items` on up to `threads` worker threads and hands each
+/// result to `emit` on the calling thread, in the same order as `items`.
+/// Results that finish early are held back until everything before them has
+/// been emitted, so output is deterministic however the work is scheduled.
+///
+/// Stops handing out new items as soon as `emit` fails, and returns its error.
+pub fn for_each_ordered<T, R, E, J, F>(
+    items: &[T],
+    threads: usize,
+    job: J,
+    mut emit: F,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+    J: Fn(&T) -> R + Sync,
+    F: FnMut(R) -> Result<(), E>,
+{
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, items.len().max(1)) {
+            let sender = sender.clone();
+            let (next, job) = (&next, &job);
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(index) {
+                    Some(item) => item,
+                    None => break,
+                };
+                if sender.send((index, job(item))).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        let mut pending = BTreeMap::new();
+        let mut to_emit = 0;
+        for (index, result) in receiver.iter() {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&to_emit) {
+                to_emit += 1;
+                if let Err(err) = emit(result) {
+                    next.store(items.len(), Ordering::Relaxed);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn emits_results_in_item_order() {
+        let items: Vec<u64> = (0..20).collect();
+        let mut emitted = Vec::new();
+
+        let result: Result<(), ()> = for_each_ordered(
+            &items,
+            4,
+            |item| {
+                // Make early items finish last.
+                thread::sleep(Duration::from_millis(20 - item));
+                item * 2
+            },
+            |doubled| {
+                emitted.push(doubled);
+                Ok(())
+            },
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            items.iter().map(|item| item * 2).collect::<Vec<_>>(),
+            emitted
+        );
+    }
+
+    #[test]
+    fn stops_at_first_failed_emit() {
+        let items: Vec<u32> = (0..100).collect();
+        let mut emitted = Vec::new();
+
+        let result = for_each_ordered(
+            &items,
+            3,
+            |item| *item,
+            |item| {
+                if item == 5 {
+                    return Err("failed");
+                }
+                emitted.push(item);
+                Ok(())
+            },
+        );
+
+        assert_eq!(Err("failed"), result);
+        assert_eq!(vec![0, 1, 2, 3, 4], emitted);
+    }
+}
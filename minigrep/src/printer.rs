@@ -0,0 +1,348 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::mem;
+use std::ops::Range;
+use std::time::Duration;
+
+use crate::{json, replace, ColorChoice, Config, Match, Sink, Stats};
+
+const MATCH: &str = "\x1b[1;31m";
+const PATH: &str = "\x1b[35m";
+const LINE_NUMBER: &str = "\x1b[32m";
+const SEPARATOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Prints matching lines along with any requested context, separating
+/// non-adjacent groups of lines with `--` the way grep does.
+pub struct Printer<'a, W: Write> {
+    config: &'a Config,
+    name: &'a str,
+    with_filename: bool,
+    out: W,
+    /// Most recent non-matching lines, kept in case a match follows them.
+    before: VecDeque<(usize, usize, String)>,
+    /// Number of trailing context lines still owed to the last match.
+    after_remaining: usize,
+    last_printed: Option<usize>,
+    /// Number of individual matches printed so far.
+    matches: usize,
+    /// Fuzzy matches waiting to be printed closest first, with how far
+    /// each is from the patterns.
+    ranked: Vec<(usize, usize, usize, String)>,
+}
+
+impl<'a, W: Write> Printer<'a, W> {
+    /// Creates a printer for the lines of the file called `name`, prefixing
+    /// each line with the name if `with_filename` is set.
+    pub fn new(config: &'a Config, name: &'a str, with_filename: bool, out: W) -> Printer<'a, W> {
+        Printer {
+            config,
+            name,
+            with_filename,
+            out,
+            before: VecDeque::new(),
+            after_remaining: 0,
+            last_printed: None,
+            matches: 0,
+            ranked: Vec::new(),
+        }
+    }
+
+    /// Marks the start of the file's output; only `--json` prints anything.
+    pub fn begin(&mut self) -> io::Result<()> {
+        if self.config.json {
+            json::begin(&mut self.out, self.name)?;
+        }
+        Ok(())
+    }
+
+    /// Marks the end of the file's output; only `--json` prints anything.
+    pub fn end(&mut self, stats: &Stats, elapsed: Duration) -> io::Result<()> {
+        if self.config.json {
+            json::end(&mut self.out, self.name, stats, elapsed)?;
+        }
+        Ok(())
+    }
+
+    /// Number of individual matches within the lines printed so far.
+    pub fn matches(&self) -> usize {
+        self.matches
+    }
+
+    /// Whether any line, matching or context, has been printed yet.
+    pub fn has_printed(&self) -> bool {
+        self.last_printed.is_some()
+    }
+
+    pub fn line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        matched: bool,
+    ) -> io::Result<()> {
+        if matched {
+            while let Some((number, offset, line)) = self.before.pop_front() {
+                self.print(number, offset, &line, '-', Vec::new())?;
+            }
+            let spans = if self.config.invert_match {
+                Vec::new()
+            } else {
+                self.config.find_iter(line)
+            };
+            self.print(number, offset, line, ':', spans)?;
+            self.after_remaining = self.config.after_context;
+        } else if self.after_remaining > 0 {
+            self.after_remaining -= 1;
+            self.print(number, offset, line, '-', Vec::new())?;
+        } else if self.config.before_context > 0 {
+            if self.before.len() == self.config.before_context {
+                self.before.pop_front();
+            }
+            self.before.push_back((number, offset, line.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Prints a `--multiline` match covering the lines of `text`, the first
+    /// of which is line `number`. `spans` are the byte ranges of the matches
+    /// within `text`.
+    pub fn region(
+        &mut self,
+        number: usize,
+        offset: usize,
+        text: &str,
+        spans: &[Range<usize>],
+    ) -> io::Result<()> {
+        while let Some((number, offset, line)) = self.before.pop_front() {
+            self.print(number, offset, &line, '-', Vec::new())?;
+        }
+        self.matches += spans.len();
+        self.after_remaining = self.config.after_context;
+        if self.config.json {
+            let end = number + text.matches('\n').count();
+            self.last_printed = Some(end);
+            return json::region(&mut self.out, self.name, number, end, offset, text, spans);
+        }
+
+        let mut start = 0;
+        for (index, line) in text.split('\n').enumerate() {
+            let next = start + line.len() + 1;
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let end = start + line.len();
+            let clipped = spans
+                .iter()
+                .filter(|span| span.start < end && span.end > start)
+                .map(|span| span.start.max(start) - start..span.end.min(end) - start)
+                .collect();
+            self.write_line(number + index, offset + start, line, ':', clipped)?;
+            start = next;
+        }
+        Ok(())
+    }
+
+    /// Prints the per-file summary used by `--count`, `--files-with-matches`
+    /// and `--files-without-match`.
+    pub fn summary(&mut self, count: usize) -> io::Result<()> {
+        if self.config.files_with_matches || self.config.files_without_match {
+            if (count > 0) == self.config.files_with_matches {
+                paint(&mut self.out, self.config, PATH, self.name)?;
+                writeln!(self.out)?;
+            }
+            Ok(())
+        } else if self.with_filename {
+            paint(&mut self.out, self.config, PATH, self.name)?;
+            paint(&mut self.out, self.config, SEPARATOR, ':')?;
+            writeln!(self.out, "{}", count)
+        } else {
+            writeln!(self.out, "{}", count)
+        }
+    }
+
+    /// Prints a selected line (`separator` is `:`) or a context line (`-`),
+    /// highlighting `spans` within it.
+    fn print(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        separator: char,
+        spans: Vec<Range<usize>>,
+    ) -> io::Result<()> {
+        let selected = separator == ':';
+        self.matches += spans.len();
+        let replaced = match &self.config.replace {
+            Some(template) if !spans.is_empty() => {
+                Some(replace::replace_line(self.config, template, line))
+            }
+            _ => None,
+        };
+        if self.config.json {
+            self.last_printed = Some(number);
+            let kind = if selected { "match" } else { "context" };
+            let replacement = replaced.as_ref().map(|(replaced, _)| replaced.as_str());
+            return json::line(
+                &mut self.out,
+                kind,
+                self.name,
+                number,
+                offset,
+                line,
+                &spans,
+                replacement,
+            );
+        }
+
+        // Highlight the replacement text in place of the matches it replaced.
+        match replaced {
+            Some((replaced, inserted)) => {
+                self.write_line(number, offset, &replaced, separator, inserted)
+            }
+            None => self.write_line(number, offset, line, separator, spans),
+        }
+    }
+
+    /// Writes one line of text output, highlighting `spans` within it. With
+    /// `--only-matching` each span is written on a line of its own instead,
+    /// and lines without any are left out.
+    fn write_line(
+        &mut self,
+        number: usize,
+        offset: usize,
+        line: &str,
+        separator: char,
+        spans: Vec<Range<usize>>,
+    ) -> io::Result<()> {
+        let config = self.config;
+        if config.only_matching {
+            for span in spans {
+                self.last_printed = Some(number);
+                self.write_prefix(number, offset + span.start, Some(span.start), separator)?;
+                paint(&mut self.out, config, MATCH, &line[span])?;
+                writeln!(self.out)?;
+            }
+            return Ok(());
+        }
+
+        let has_context = self.config.before_context > 0 || self.config.after_context > 0;
+        let follows_last = self.last_printed.is_some_and(|last| last + 1 == number);
+        if has_context && self.has_printed() && !follows_last {
+            group_separator(&mut self.out, self.config)?;
+        }
+        self.last_printed = Some(number);
+        let column = spans.first().map(|span| span.start);
+        self.write_prefix(number, offset, column, separator)?;
+
+        if config.color == ColorChoice::Always {
+            let mut printed = 0;
+            for span in spans {
+                write!(self.out, "{}", &line[printed..span.start])?;
+                paint(&mut self.out, config, MATCH, &line[span.clone()])?;
+                printed = span.end;
+            }
+            writeln!(self.out, "{}", &line[printed..])
+        } else {
+            writeln!(self.out, "{}", line)
+        }
+    }
+
+    /// Writes the file name, line number, column and byte offset that come
+    /// before a line, as far as they were asked for. `column` is the 0-based
+    /// start of the first match, if the line has one.
+    fn write_prefix(
+        &mut self,
+        number: usize,
+        offset: usize,
+        column: Option<usize>,
+        separator: char,
+    ) -> io::Result<()> {
+        let config = self.config;
+        if self.with_filename {
+            paint(&mut self.out, config, PATH, self.name)?;
+            paint(&mut self.out, config, SEPARATOR, separator)?;
+        }
+        if config.line_number {
+            paint(&mut self.out, config, LINE_NUMBER, number)?;
+            paint(&mut self.out, config, SEPARATOR, separator)?;
+        }
+        if let Some(column) = column.filter(|_| config.column) {
+            paint(&mut self.out, config, LINE_NUMBER, column + 1)?;
+            paint(&mut self.out, config, SEPARATOR, separator)?;
+        }
+        if config.byte_offset {
+            paint(&mut self.out, config, LINE_NUMBER, offset)?;
+            paint(&mut self.out, config, SEPARATOR, separator)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the lines found by a `Searcher`. Context has already been chosen
+/// by the searcher, so it is printed as it arrives rather than buffered.
+impl<W: Write> Sink for Printer<'_, W> {
+    fn matched(&mut self, found: &Match) -> io::Result<bool> {
+        let config = self.config;
+        if config.stops_at_first_match() {
+            return Ok(false);
+        }
+        if config.summarizes() {
+            return Ok(true);
+        }
+        // Fuzzy matches are listed closest first, unless context lines need
+        // them in file order.
+        let has_context = config.before_context > 0 || config.after_context > 0;
+        if config.fuzzy.is_some() && !config.invert_match && !has_context {
+            self.ranked.push((
+                config.matcher.distance(found.line),
+                found.line_number,
+                found.byte_offset,
+                found.line.to_string(),
+            ));
+            return Ok(true);
+        }
+        let spans = found.spans.clone();
+        self.print(found.line_number, found.byte_offset, found.line, ':', spans)?;
+        Ok(true)
+    }
+
+    fn context(&mut self, line: &Match) -> io::Result<bool> {
+        self.print(
+            line.line_number,
+            line.byte_offset,
+            line.line,
+            '-',
+            Vec::new(),
+        )?;
+        Ok(true)
+    }
+
+    fn finish(&mut self, _stats: &Stats) -> io::Result<()> {
+        let mut ranked = mem::take(&mut self.ranked);
+        ranked.sort_by_key(|&(distance, ..)| distance);
+        for (_, number, offset, line) in ranked {
+            let spans = self.config.find_iter(&line);
+            self.print(number, offset, &line, ':', spans)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the `--` line that separates non-adjacent groups of lines.
+pub fn group_separator<W: Write>(out: &mut W, config: &Config) -> io::Result<()> {
+    paint(out, config, SEPARATOR, "--")?;
+    writeln!(out)
+}
+
+fn paint<W: Write, T: Display>(
+    out: &mut W,
+    config: &Config,
+    color: &str,
+    text: T,
+) -> io::Result<()> {
+    if config.color == ColorChoice::Always {
+        write!(out, "{}{}{}", color, text, RESET)
+    } else {
+        write!(out, "{}", text)
+    }
+}
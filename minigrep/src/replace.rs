@@ -0,0 +1,161 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process;
+use std::str;
+
+use crate::{walk, Config};
+
+/// Replaces every match in `line` with `template`, returning the new line
+/// and the byte ranges of the replacements within it.
+///
+/// With a regular expression, the template can refer to capture groups as
+/// `$1` or `${name}`; with a fixed string, `$0` stands for the matched text.
+pub fn replace_line(config: &Config, template: &str, line: &str) -> (String, Vec<Range<usize>>) {
+    let mut replaced = String::with_capacity(line.len());
+    let mut inserted = Vec::new();
+    let mut copied = 0;
+
+    if let Some(pattern) = config.matcher.as_regex() {
+        for captures in pattern.captures_iter(line) {
+            let found = captures.get(0).unwrap();
+            replaced.push_str(&line[copied..found.start()]);
+            let start = replaced.len();
+            captures.expand(template, &mut replaced);
+            inserted.push(start..replaced.len());
+            copied = found.end();
+        }
+    } else {
+        for span in config.find_iter(line) {
+            replaced.push_str(&line[copied..span.start]);
+            let start = replaced.len();
+            replaced.push_str(&template.replace("$0", &line[span.clone()]));
+            inserted.push(start..replaced.len());
+            copied = span.end;
+        }
+    }
+
+    replaced.push_str(&line[copied..]);
+    (replaced, inserted)
+}
+
+/// Applies the `--replace` template to every line of the file at `path`
+/// and returns the number of lines changed.
+///
+/// The new contents are written to a temporary file alongside the original,
+/// which is then renamed over it, so readers never see a half-written file.
+/// Before that the original is copied to a backup named with the `--in-place`
+/// suffix. Files with nothing to replace are left untouched.
+pub fn rewrite_file(config: &Config, path: &Path) -> io::Result<usize> {
+    let template = config.replace.as_deref().unwrap_or_default();
+    let suffix = config.in_place.as_deref().unwrap_or_default();
+
+    let mut reader = BufReader::new(File::open(path)?);
+    if walk::is_binary(reader.fill_buf()?) {
+        return Ok(0);
+    }
+
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return Err(io::Error::other("can't rewrite a directory")),
+    };
+    let temp_path = path.with_file_name(format!(".{}.minigrep-{}", name, process::id()));
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+
+    let changed = match copy_replaced(config, template, reader, &mut writer) {
+        Ok(changed) => changed,
+        Err(err) => {
+            drop(writer);
+            let _ = fs::remove_file(&temp_path);
+            return Err(err);
+        }
+    };
+    drop(writer);
+    if changed == 0 {
+        fs::remove_file(&temp_path)?;
+        return Ok(0);
+    }
+
+    let mut backup = OsString::from(path);
+    backup.push(suffix);
+    fs::set_permissions(&temp_path, fs::metadata(path)?.permissions())?;
+    fs::copy(path, &backup)?;
+    fs::rename(&temp_path, path)?;
+    Ok(changed)
+}
+
+fn copy_replaced<R: BufRead, W: Write>(
+    config: &Config,
+    template: &str,
+    mut reader: R,
+    writer: &mut W,
+) -> io::Result<usize> {
+    let mut buffer = Vec::new();
+    let mut changed = 0;
+    loop {
+        buffer.clear();
+        if reader.read_until(b'\n', &mut buffer)? == 0 {
+            break;
+        }
+
+        let text = str::from_utf8(&buffer)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let content = text.trim_end_matches(['\r', '\n']);
+        let (replaced, inserted) = replace_line(config, template, content);
+        if !inserted.is_empty() && replaced != content {
+            changed += 1;
+        }
+        writer.write_all(replaced.as_bytes())?;
+        writer.write_all(&buffer[content.len()..])?;
+    }
+    writer.flush()?;
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{config, TempDir};
+
+    #[test]
+    fn expands_capture_groups() {
+        let config = config(&[r"(?P<key>\w+)=(\d+)", "poem.txt"]);
+
+        assert_eq!(
+            (String::from("let a = 1; let bc = 23;"), vec![0..9, 11..22]),
+            replace_line(&config, "let ${key} = $2", "a=1; bc=23;")
+        );
+    }
+
+    #[test]
+    fn fixed_strings_replace_literally() {
+        let config = config(&["-F", "-i", "a.b", "poem.txt"]);
+
+        assert_eq!(
+            (String::from("[A.B] axb [a.b]"), vec![0..5, 10..15]),
+            replace_line(&config, "[$0]", "A.B axb a.b")
+        );
+    }
+
+    #[test]
+    fn rewrites_in_place_with_backup() {
+        let dir = TempDir::new("replace");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "colour: red\r\nno change\ncolour: blue").unwrap();
+
+        let config = config(&["-r", "color", "--in-place=.orig", "colou?r", "notes.txt"]);
+        assert_eq!(2, rewrite_file(&config, &path).unwrap());
+
+        assert_eq!(
+            "color: red\r\nno change\ncolor: blue",
+            fs::read_to_string(&path).unwrap()
+        );
+        assert_eq!(
+            "colour: red\r\nno change\ncolour: blue",
+            fs::read_to_string(dir.join("notes.txt.orig")).unwrap()
+        );
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+    }
+}
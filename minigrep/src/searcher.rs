@@ -0,0 +1,350 @@
+//! The search loop as a library: a `Searcher` reads lines from any reader,
+//! finds the selected ones with a `Matcher`, and hands them to a `Sink`
+//! instead of printing them, so that other programs can use minigrep's
+//! search without going through its command line or capturing stdout.
+//!
+//! ```
+//! use minigrep::{Match, Matcher, Searcher, Sink};
+//! use std::io;
+//!
+//! struct Lines(Vec<usize>);
+//!
+//! impl Sink for Lines {
+//!     fn matched(&mut self, found: &Match) -> io::Result<bool> {
+//!         self.0.push(found.line_number);
+//!         Ok(true)
+//!     }
+//! }
+//!
+//! let matcher = Matcher::regex("fast|three").unwrap();
+//! let mut lines = Lines(Vec::new());
+//! let contents = "Rust:\nsafe, fast, productive.\nPick three.\n";
+//! Searcher::builder()
+//!     .build()
+//!     .search(&matcher, contents.as_bytes(), &mut lines)
+//!     .unwrap();
+//! assert_eq!(vec![2, 3], lines.0);
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+use std::str;
+
+use crate::{Match, Matcher, Stats};
+
+/// Receives the results of a `Searcher`. Lines are passed as `Match`es
+/// whose `spans` are every match of the matcher within the line, which is
+/// empty for lines selected by `invert_match` and for most context lines.
+pub trait Sink {
+    /// Called with each selected line, in order. Returning `false` stops
+    /// the search.
+    fn matched(&mut self, found: &Match) -> io::Result<bool>;
+
+    /// Called with each line of leading or trailing context. Returning
+    /// `false` stops the search.
+    fn context(&mut self, _line: &Match) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    /// Called once the search is over, unless it failed.
+    fn finish(&mut self, _stats: &Stats) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Configures a `Searcher`. Every option is off by default.
+#[derive(Clone, Debug, Default)]
+pub struct SearcherBuilder {
+    searcher: Searcher,
+}
+
+impl SearcherBuilder {
+    pub fn new() -> SearcherBuilder {
+        SearcherBuilder::default()
+    }
+
+    /// Selects the lines that don't match, like `-v`.
+    pub fn invert_match(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.searcher.invert_match = yes;
+        self
+    }
+
+    /// Reports this many lines of context before each selected line.
+    pub fn before_context(&mut self, lines: usize) -> &mut SearcherBuilder {
+        self.searcher.before_context = lines;
+        self
+    }
+
+    /// Reports this many lines of context after each selected line.
+    pub fn after_context(&mut self, lines: usize) -> &mut SearcherBuilder {
+        self.searcher.after_context = lines;
+        self
+    }
+
+    /// Stops after this many selected lines, apart from any trailing
+    /// context still owed to the last of them.
+    pub fn max_count(&mut self, max: Option<usize>) -> &mut SearcherBuilder {
+        self.searcher.max_count = max;
+        self
+    }
+
+    /// Replaces invalid UTF-8 with U+FFFD instead of failing with an
+    /// `InvalidData` error.
+    pub fn lossy(&mut self, yes: bool) -> &mut SearcherBuilder {
+        self.searcher.lossy = yes;
+        self
+    }
+
+    pub fn build(&self) -> Searcher {
+        self.searcher.clone()
+    }
+}
+
+/// Searches input line by line. Built with `Searcher::builder`, and can be
+/// reused for any number of searches.
+#[derive(Clone, Debug, Default)]
+pub struct Searcher {
+    invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    max_count: Option<usize>,
+    lossy: bool,
+}
+
+impl Searcher {
+    pub fn builder() -> SearcherBuilder {
+        SearcherBuilder::new()
+    }
+
+    /// Searches every line of `reader` with `matcher`, reporting selected
+    /// lines and their context to `sink`. Returns the statistics for the
+    /// search, which are also passed to `Sink::finish`.
+    pub fn search<R: BufRead, S: Sink>(
+        &self,
+        matcher: &Matcher,
+        mut reader: R,
+        sink: &mut S,
+    ) -> io::Result<Stats> {
+        let mut stats = Stats {
+            files_searched: 1,
+            ..Stats::default()
+        };
+        let mut buffer = Vec::new();
+        // Recent unselected lines, kept in case a selected line follows.
+        let mut before: VecDeque<(usize, usize, String)> = VecDeque::new();
+        let mut after_remaining = 0;
+        let mut number = 0;
+        loop {
+            let limited = self.max_count.is_some_and(|max| stats.matched_lines >= max);
+            if limited && after_remaining == 0 {
+                break;
+            }
+            buffer.clear();
+            let read = reader.read_until(b'\n', &mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            number += 1;
+
+            let line = if self.lossy {
+                String::from_utf8_lossy(&buffer)
+            } else {
+                str::from_utf8(&buffer)
+                    .map(Into::into)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+            };
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let offset = stats.bytes_searched;
+            stats.bytes_searched += read;
+
+            let selected = !limited && matcher.find_at(line, 0).is_some() != self.invert_match;
+            let keep_going = if selected {
+                stats.matched_lines += 1;
+                let mut keep_going = true;
+                while let Some((number, offset, line)) = before.pop_front() {
+                    if !sink.context(&found(matcher, number, offset, &line))? {
+                        keep_going = false;
+                        break;
+                    }
+                }
+                let found = found(matcher, number, offset, line);
+                stats.matches += found.spans.len();
+                after_remaining = self.after_context;
+                keep_going && sink.matched(&found)?
+            } else if after_remaining > 0 {
+                after_remaining -= 1;
+                sink.context(&found(matcher, number, offset, line))?
+            } else {
+                if self.before_context > 0 {
+                    if before.len() == self.before_context {
+                        before.pop_front();
+                    }
+                    before.push_back((number, offset, line.to_string()));
+                }
+                true
+            };
+            if !keep_going {
+                break;
+            }
+        }
+
+        if stats.matched_lines > 0 {
+            stats.files_with_matches = 1;
+        }
+        sink.finish(&stats)?;
+        Ok(stats)
+    }
+}
+
+fn found<'a>(
+    matcher: &Matcher,
+    line_number: usize,
+    byte_offset: usize,
+    line: &'a str,
+) -> Match<'a> {
+    Match {
+        line_number,
+        byte_offset,
+        spans: matcher.find_iter(line),
+        line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records each line as `number:line` or `number-line`, grep style.
+    #[derive(Default)]
+    struct Lines {
+        lines: Vec<String>,
+        stop_after: Option<usize>,
+        finished: Option<Stats>,
+    }
+
+    impl Sink for Lines {
+        fn matched(&mut self, found: &Match) -> io::Result<bool> {
+            self.lines
+                .push(format!("{}:{}", found.line_number, found.line));
+            Ok(self.stop_after != Some(self.lines.len()))
+        }
+
+        fn context(&mut self, line: &Match) -> io::Result<bool> {
+            self.lines
+                .push(format!("{}-{}", line.line_number, line.line));
+            Ok(true)
+        }
+
+        fn finish(&mut self, stats: &Stats) -> io::Result<()> {
+            self.finished = Some(*stats);
+            Ok(())
+        }
+    }
+
+    const POEM: &str = "I'm nobody! Who are you?\nAre you nobody, too?\nThen there's a pair of us - don't tell!\nThey'd banish us, you know.\n\nHow dreary to be somebody!\nHow public, like a frog\n";
+
+    fn search(builder: &SearcherBuilder, pattern: &str) -> Lines {
+        let matcher = Matcher::regex(pattern).unwrap();
+        let mut sink = Lines::default();
+        builder
+            .build()
+            .search(&matcher, POEM.as_bytes(), &mut sink)
+            .unwrap();
+        sink
+    }
+
+    #[test]
+    fn reports_matches_with_their_spans_and_stats() {
+        struct Spans<'a>(&'a mut Vec<Vec<std::ops::Range<usize>>>);
+        impl Sink for Spans<'_> {
+            fn matched(&mut self, found: &Match) -> io::Result<bool> {
+                self.0.push(found.spans.clone());
+                Ok(true)
+            }
+        }
+
+        let matcher = Matcher::regex("body").unwrap();
+        let mut spans = Vec::new();
+
+        let stats = Searcher::builder()
+            .build()
+            .search(
+                &matcher,
+                "nobody, somebody\nnone\n".as_bytes(),
+                &mut Spans(&mut spans),
+            )
+            .unwrap();
+
+        assert_eq!(vec![vec![2..6, 12..16]], spans);
+        assert_eq!(1, stats.matched_lines);
+        assert_eq!(2, stats.matches);
+        assert_eq!(22, stats.bytes_searched);
+    }
+
+    #[test]
+    fn reports_context_around_matches() {
+        let sink = search(
+            Searcher::builder().before_context(1).after_context(1),
+            "frog|pair",
+        );
+
+        assert_eq!(
+            vec![
+                "2-Are you nobody, too?",
+                "3:Then there's a pair of us - don't tell!",
+                "4-They'd banish us, you know.",
+                "6-How dreary to be somebody!",
+                "7:How public, like a frog",
+            ],
+            sink.lines
+        );
+        assert_eq!(2, sink.finished.unwrap().matched_lines);
+    }
+
+    #[test]
+    fn inverts_and_limits_the_selection() {
+        let sink = search(
+            Searcher::builder().invert_match(true).max_count(Some(2)),
+            "body|us",
+        );
+
+        assert_eq!(vec!["5:", "7:How public, like a frog"], sink.lines);
+    }
+
+    #[test]
+    fn sink_can_stop_the_search() {
+        let matcher = Matcher::regex("nobody").unwrap();
+        let mut sink = Lines {
+            stop_after: Some(1),
+            ..Lines::default()
+        };
+        let stats = Searcher::builder()
+            .build()
+            .search(&matcher, POEM.as_bytes(), &mut sink)
+            .unwrap();
+
+        assert_eq!(vec!["1:I'm nobody! Who are you?"], sink.lines);
+        assert_eq!(1, stats.matched_lines);
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error_unless_lossy() {
+        let matcher = Matcher::regex("caf").unwrap();
+        let input = &b"caf\xe9\n"[..];
+
+        let err = Searcher::builder()
+            .build()
+            .search(&matcher, input, &mut Lines::default())
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+
+        let mut sink = Lines::default();
+        Searcher::builder()
+            .lossy(true)
+            .build()
+            .search(&matcher, input, &mut sink)
+            .unwrap();
+        assert_eq!(vec!["1:caf\u{FFFD}"], sink.lines);
+    }
+}
@@ -0,0 +1,202 @@
+//! The configuration file, `~/.config/minigrep/config.toml`. Its keys are
+//! the long names of command line options, so
+//!
+//! ```toml
+//! ignore-case = true
+//! glob = ["!*.min.js"]
+//!
+//! [profile.logs]
+//! glob = ["*.log", "*.log.gz"]
+//! search-zip = true
+//! color = "always"
+//! ```
+//!
+//! applies `--ignore-case --glob '!*.min.js'` to every search, and adds the
+//! `logs` options as well when run with `--profile logs`. Options on the
+//! command line are applied last, so they override the file.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+/// An option as `Parser::set` takes it: a long name and maybe a value.
+pub type Setting = (String, Option<String>);
+
+/// Options that only make sense on the command line.
+const COMMAND_LINE_ONLY: &[&str] = &["regexp", "file", "help", "version", "profile", "no-config"];
+
+/// Flags that can be set to `false`, and the flag that turns each one off.
+const NEGATIONS: &[(&str, &str)] = &[
+    ("ignore-case", "case-sensitive"),
+    ("case-sensitive", "ignore-case"),
+];
+
+/// Options read from a configuration file.
+#[derive(Debug, Default)]
+pub struct Settings {
+    defaults: Vec<Setting>,
+    profiles: HashMap<String, Vec<Setting>>,
+}
+
+impl Settings {
+    /// Reads the configuration file named by `MINIGREP_CONFIG`, or else
+    /// `minigrep/config.toml` in `XDG_CONFIG_HOME` or `~/.config`. A missing
+    /// file is the same as an empty one.
+    pub fn load() -> Result<Settings, String> {
+        let path = match path() {
+            Some(path) => path,
+            None => return Ok(Settings::default()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Settings::parse(&text)
+                .map_err(|err| format!("Invalid config file {}: {}", path.display(), err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(err) => Err(format!(
+                "Couldn't read config file {}: {}",
+                path.display(),
+                err
+            )),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Settings, String> {
+        let mut table: Table = text
+            .parse()
+            .map_err(|err: toml::de::Error| err.message().to_string())?;
+        let mut profiles = HashMap::new();
+        match table.remove("profile") {
+            Some(Value::Table(tables)) => {
+                for (name, profile) in tables {
+                    match profile {
+                        Value::Table(profile) => {
+                            let settings = settings(profile)
+                                .map_err(|err| format!("in profile '{}': {}", name, err))?;
+                            profiles.insert(name, settings);
+                        }
+                        _ => return Err(format!("profile '{}' must be a table", name)),
+                    }
+                }
+            }
+            Some(_) => return Err(String::from("'profile' must be a table of profiles")),
+            None => {}
+        }
+
+        Ok(Settings {
+            defaults: settings(table)?,
+            profiles,
+        })
+    }
+
+    /// Returns the options to apply before the command line: the defaults,
+    /// followed by those of `profile` if one was chosen.
+    pub fn options(&self, profile: Option<&str>) -> Result<Vec<Setting>, String> {
+        let mut options = self.defaults.clone();
+        if let Some(name) = profile {
+            match self.profiles.get(name) {
+                Some(profile) => options.extend(profile.iter().cloned()),
+                None => return Err(format!("Unknown profile '{}'", name)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("MINIGREP_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("minigrep").join("config.toml"))
+}
+
+/// Turns a table of options into settings: `true` stands for a flag, and an
+/// array for an option given once per element. `false` stands for the flag
+/// that undoes it, so a profile can turn off `ignore-case` from the defaults;
+/// it's an error for flags that can't be undone.
+fn settings(table: Table) -> Result<Vec<Setting>, String> {
+    let mut settings = Vec::new();
+    for (name, value) in table {
+        if COMMAND_LINE_ONLY.contains(&name.as_str()) {
+            return Err(format!("'{}' can only be given on the command line", name));
+        }
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                Value::Boolean(true) => None,
+                Value::Boolean(false) => match NEGATIONS.iter().find(|(flag, _)| *flag == name) {
+                    Some((_, negation)) => {
+                        settings.push((negation.to_string(), None));
+                        continue;
+                    }
+                    None => return Err(format!("'{}' can't be turned off with false", name)),
+                },
+                Value::String(value) => Some(value),
+                Value::Integer(value) => Some(value.to_string()),
+                _ => return Err(format!("unsupported value for '{}'", name)),
+            };
+            settings.push((name.clone(), value));
+        }
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(name: &str, value: Option<&str>) -> Setting {
+        (name.to_string(), value.map(String::from))
+    }
+
+    #[test]
+    fn reads_defaults_and_profiles() {
+        let settings = Settings::parse(
+            r#"
+            ignore-case = true
+            context = 2
+            glob = ["!*.min.js", "!vendor/"]
+
+            [profile.logs]
+            glob = "*.log"
+            color = "always"
+            ignore-case = false
+            "#,
+        )
+        .unwrap();
+
+        let defaults = vec![
+            setting("context", Some("2")),
+            setting("glob", Some("!*.min.js")),
+            setting("glob", Some("!vendor/")),
+            setting("ignore-case", None),
+        ];
+        assert_eq!(defaults, settings.options(None).unwrap());
+
+        let mut logs = defaults;
+        logs.push(setting("color", Some("always")));
+        logs.push(setting("glob", Some("*.log")));
+        logs.push(setting("case-sensitive", None));
+        assert_eq!(logs, settings.options(Some("logs")).unwrap());
+
+        assert!(settings.options(Some("nope")).is_err());
+    }
+
+    #[test]
+    fn rejects_command_line_only_options() {
+        assert!(Settings::parse("regexp = \"fn main\"").is_err());
+        assert!(Settings::parse("[profile.x]\nhelp = true").is_err());
+        assert!(Settings::parse("profile = \"logs\"").is_err());
+        assert!(Settings::parse("context = 1.5").is_err());
+        assert!(Settings::parse("hidden = false")
+            .unwrap_err()
+            .contains("'hidden'"));
+    }
+}
@@ -0,0 +1,61 @@
+//! Helpers shared by the unit tests.
+
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::settings::Settings;
+use crate::{args, Config};
+
+/// Builds a command line from `args`, with the program name in front.
+pub fn args(args: &[&str]) -> Vec<String> {
+    std::iter::once("minigrep")
+        .chain(args.iter().cloned())
+        .map(String::from)
+        .collect()
+}
+
+/// Parses `args` as a command line without the configuration file, and as
+/// if `CASE_INSENSITIVE` weren't set, so results don't depend on who runs
+/// the tests.
+pub fn config(args: &[&str]) -> Config {
+    let args = args.iter().map(|arg| arg.to_string());
+    args::parse_with_env(args, &Settings::default(), false).unwrap()
+}
+
+/// A fresh, empty directory under the system's temporary directory, which
+/// is deleted along with its contents when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates the directory. `name` must be unique among the tests, and
+    /// the process ID keeps concurrent runs apart.
+    pub fn new(name: &str) -> TempDir {
+        let dir = env::temp_dir().join(format!("minigrep-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
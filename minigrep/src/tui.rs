@@ -0,0 +1,592 @@
+//! `--tui`: an interactive, full-screen view of the results. Every file is
+//! read once up front, and each edit to the query re-runs the search over
+//! the lines in memory. Enter opens the selected match in `$VISUAL` or
+//! `$EDITOR`.
+
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{mapped, walk, Config, Matcher};
+
+/// Most matching lines kept, so that a short query over a large tree stays
+/// responsive.
+const MAX_HITS: usize = 10_000;
+
+/// Number of lines moved by Page Up and Page Down.
+const PAGE: isize = 10;
+
+const HELP: &str = "↑/↓ select  Enter open in $EDITOR  Ctrl-U clear  Esc quit";
+
+/// Runs the interface over `files` until the user quits. Returns whether
+/// the final query matched anything.
+pub fn run(config: &Config, files: &[PathBuf]) -> io::Result<bool> {
+    let mut app = App::load(config, files);
+    let mut terminal = ratatui::try_init()?;
+    let result = app.event_loop(&mut terminal);
+    ratatui::try_restore()?;
+    result.map(|()| !app.hits.is_empty())
+}
+
+/// A searched file, split into lines.
+struct Document {
+    path: PathBuf,
+    name: String,
+    lines: Vec<String>,
+}
+
+impl Document {
+    /// Reads the file at `path`, decoding it the way the search would.
+    /// Returns `None` for binary files.
+    fn read(config: &Config, path: &Path) -> io::Result<Option<Document>> {
+        let name = path.display().to_string();
+        let mut bytes = Vec::new();
+        File::open(path)
+            .and_then(|file| {
+                DecodeReaderBytesBuilder::new()
+                    .encoding(config.encoding)
+                    .bom_override(true)
+                    .utf8_passthru(true)
+                    .strip_bom(true)
+                    .build(file)
+                    .read_to_end(&mut bytes)
+            })
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", name, err)))?;
+        if walk::is_binary(&bytes[..bytes.len().min(mapped::SNIFF_LEN)]) {
+            return Ok(None);
+        }
+
+        let lines = String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(String::from)
+            .collect();
+        Ok(Some(Document {
+            path: path.to_path_buf(),
+            name,
+            lines,
+        }))
+    }
+}
+
+/// A selected line: an index into `App::documents` and one into its lines.
+#[derive(Debug, PartialEq)]
+struct Hit {
+    document: usize,
+    line: usize,
+    spans: Vec<Range<usize>>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Continue,
+    Quit,
+    /// Open the document at the given one-based line number.
+    Edit(usize, usize),
+}
+
+struct App<'a> {
+    config: &'a Config,
+    documents: Vec<Document>,
+    query: String,
+    /// The query that `hits` were found with.
+    searched: Option<String>,
+    hits: Vec<Hit>,
+    list: ListState,
+    /// Why the query couldn't be used, or the editor couldn't be run.
+    error: Option<String>,
+}
+
+impl<'a> App<'a> {
+    fn new(config: &'a Config, documents: Vec<Document>) -> App<'a> {
+        let mut app = App {
+            config,
+            documents,
+            query: config.patterns.first().cloned().unwrap_or_default(),
+            searched: None,
+            hits: Vec::new(),
+            list: ListState::default(),
+            error: None,
+        };
+        app.search();
+        app
+    }
+
+    /// Reads `files` and starts the interface over them. Files that can't
+    /// be read are left out and mentioned in the status line.
+    fn load(config: &'a Config, files: &[PathBuf]) -> App<'a> {
+        let mut documents = Vec::new();
+        let mut unreadable = Vec::new();
+        for path in files {
+            match Document::read(config, path) {
+                Ok(Some(document)) => documents.push(document),
+                Ok(None) => {}
+                Err(err) => unreadable.push(err),
+            }
+        }
+
+        let mut app = App::new(config, documents);
+        app.error = match unreadable.as_slice() {
+            [] => None,
+            [err] => Some(format!("Couldn't read {}", err)),
+            [err, ..] => Some(format!(
+                "Couldn't read {} files, including {}",
+                unreadable.len(),
+                err
+            )),
+        };
+        app
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match self.key(key) {
+                Action::Continue => {}
+                Action::Quit => return Ok(()),
+                Action::Edit(document, line) => {
+                    ratatui::try_restore()?;
+                    let status = editor(&self.documents[document].path, line).status();
+                    *terminal = ratatui::try_init()?;
+                    self.reload(document);
+                    // Set after reloading, whose search clears the status line.
+                    match status {
+                        Ok(status) if status.success() => {}
+                        Ok(status) => self.error = Some(format!("Editor exited with {}", status)),
+                        Err(err) => self.error = Some(format!("Couldn't run editor: {}", err)),
+                    }
+                }
+            }
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent) -> Action {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('c') if control => return Action::Quit,
+            KeyCode::Char('u') if control => {
+                self.query.clear();
+                self.search();
+            }
+            KeyCode::Char('p') if control => self.select(-1),
+            KeyCode::Char('n') if control => self.select(1),
+            KeyCode::Char(c) if !control => {
+                self.query.push(c);
+                self.search();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.search();
+            }
+            KeyCode::Up => self.select(-1),
+            KeyCode::Down => self.select(1),
+            KeyCode::PageUp => self.select(-PAGE),
+            KeyCode::PageDown => self.select(PAGE),
+            KeyCode::Enter => {
+                if let Some(hit) = self.selected() {
+                    return Action::Edit(hit.document, hit.line + 1);
+                }
+            }
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    fn selected(&self) -> Option<&Hit> {
+        self.list.selected().and_then(|index| self.hits.get(index))
+    }
+
+    /// Moves the selection by `delta` lines, stopping at either end.
+    fn select(&mut self, delta: isize) {
+        if let Some(selected) = self.list.selected() {
+            let last = self.hits.len().saturating_sub(1) as isize;
+            let selected = (selected as isize + delta).clamp(0, last);
+            self.list.select(Some(selected as usize));
+        }
+    }
+
+    /// Re-runs the search for the current query. A query containing the
+    /// previous one as a literal can only match lines that already
+    /// matched, so only those are searched again.
+    fn search(&mut self) {
+        if self.query.is_empty() {
+            self.hits.clear();
+            self.searched = None;
+            self.error = None;
+            self.list.select(None);
+            return;
+        }
+        let matcher = match self.matcher() {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                self.error = Some(err);
+                return;
+            }
+        };
+        self.error = None;
+
+        let narrows = self.narrows();
+        let invert = self.config.invert_match;
+        let test = |document: usize, line: usize, text: &str| {
+            let found = matcher.find_at(text, 0).is_some();
+            (found != invert).then(|| Hit {
+                document,
+                line,
+                spans: if invert {
+                    Vec::new()
+                } else {
+                    matcher.find_iter(text)
+                },
+            })
+        };
+        self.hits = if narrows {
+            self.hits
+                .iter()
+                .filter_map(|hit| {
+                    test(
+                        hit.document,
+                        hit.line,
+                        &self.documents[hit.document].lines[hit.line],
+                    )
+                })
+                .collect()
+        } else {
+            self.documents
+                .iter()
+                .enumerate()
+                .flat_map(|(document, contents)| {
+                    contents
+                        .lines
+                        .iter()
+                        .enumerate()
+                        .map(move |(line, text)| (document, line, text))
+                })
+                .filter_map(|(document, line, text)| test(document, line, text))
+                .take(MAX_HITS)
+                .collect()
+        };
+        self.searched = Some(self.query.clone());
+        self.list
+            .select(if self.hits.is_empty() { None } else { Some(0) });
+    }
+
+    fn narrows(&self) -> bool {
+        let config = self.config;
+        let literal = |query: &str| config.fixed_strings || regex::escape(query) == query;
+        let plain = config.case_sensitive
+            && !config.invert_match
+            && !config.word_regexp
+            && !config.line_regexp
+            && config.fuzzy.is_none();
+        plain
+            && self.hits.len() < MAX_HITS
+            && self.searched.as_deref().is_some_and(|previous| {
+                self.query.contains(previous) && literal(previous) && literal(&self.query)
+            })
+    }
+
+    /// Builds a matcher for the current query with the options given on the
+    /// command line.
+    fn matcher(&self) -> Result<Matcher, String> {
+        let config = Config {
+            patterns: vec![self.query.clone()],
+            case_sensitive: self.config.case_sensitive,
+            fixed_strings: self.config.fixed_strings,
+            word_regexp: self.config.word_regexp,
+            line_regexp: self.config.line_regexp,
+            fuzzy: self.config.fuzzy,
+            ..Config::default()
+        };
+        Matcher::new(&config)
+    }
+
+    /// Reads a document again after it has been edited. If it can no longer
+    /// be read, the old contents are kept and the error is shown.
+    fn reload(&mut self, document: usize) {
+        let path = self.documents[document].path.clone();
+        let unreadable = match Document::read(self.config, &path) {
+            Ok(reloaded) => {
+                if let Some(reloaded) = reloaded {
+                    self.documents[document] = reloaded;
+                }
+                None
+            }
+            Err(err) => Some(format!("Couldn't read {}", err)),
+        };
+        let selected = self.list.selected();
+        self.searched = None;
+        self.search();
+        if selected.is_some() && !self.hits.is_empty() {
+            self.list
+                .select(selected.map(|index| index.min(self.hits.len() - 1)));
+        }
+        if unreadable.is_some() {
+            self.error = unreadable;
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [input, body, status] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [results, preview] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(body);
+
+        let prompt = Line::from(vec![
+            Span::styled("> ", Style::new().add_modifier(Modifier::BOLD)),
+            Span::raw(self.query.as_str()),
+        ]);
+        frame.render_widget(Paragraph::new(prompt), input);
+        let cursor = input.x + 2 + self.query.chars().count() as u16;
+        frame.set_cursor_position((cursor.min(input.right().saturating_sub(1)), input.y));
+
+        let documents = &self.documents;
+        let items: Vec<ListItem> = self
+            .hits
+            .iter()
+            .map(|hit| {
+                let document = &documents[hit.document];
+                let mut line = vec![
+                    Span::styled(document.name.as_str(), Style::new().fg(Color::Magenta)),
+                    Span::raw(":"),
+                    Span::styled((hit.line + 1).to_string(), Style::new().fg(Color::Green)),
+                    Span::raw(":"),
+                ];
+                line.extend(highlight(&document.lines[hit.line], &hit.spans));
+                ListItem::new(Line::from(line))
+            })
+            .collect();
+        let count = if self.hits.len() == MAX_HITS {
+            format!(" {}+ matches ", MAX_HITS)
+        } else {
+            format!(" {} matches ", self.hits.len())
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(count))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, results, &mut self.list);
+
+        self.draw_preview(frame, preview);
+
+        let status_line = match &self.error {
+            Some(err) => Span::styled(err.as_str(), Style::new().fg(Color::Red)),
+            None => Span::styled(HELP, Style::new().add_modifier(Modifier::DIM)),
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+
+    /// Shows the lines around the selected match, keeping it in the middle
+    /// of the pane where possible.
+    fn draw_preview(&self, frame: &mut Frame, area: Rect) {
+        let hit = match self.selected() {
+            Some(hit) => hit,
+            None => {
+                frame.render_widget(Block::bordered(), area);
+                return;
+            }
+        };
+        let document = &self.documents[hit.document];
+        let height = area.height.saturating_sub(2) as usize;
+        let first = hit.line.saturating_sub(height / 2);
+        let width = (first + height).min(document.lines.len()).to_string().len();
+
+        let lines: Vec<Line> = document
+            .lines
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .map(|(index, text)| {
+                let number = format!("{:>width$} ", index + 1, width = width);
+                if index == hit.line {
+                    let mut line = vec![Span::styled(number, Style::new().fg(Color::Green))];
+                    line.extend(highlight(text, &hit.spans));
+                    Line::from(line).style(Style::new().add_modifier(Modifier::BOLD))
+                } else {
+                    Line::from(vec![
+                        Span::styled(number, Style::new().add_modifier(Modifier::DIM)),
+                        Span::raw(text.as_str()),
+                    ])
+                }
+            })
+            .collect();
+        let block = Block::bordered().title(format!(" {} ", document.name));
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+}
+
+/// Splits `text` into spans with the matches in `spans` highlighted.
+fn highlight<'t>(text: &'t str, spans: &[Range<usize>]) -> Vec<Span<'t>> {
+    let style = Style::new().fg(Color::Red).add_modifier(Modifier::BOLD);
+    let mut pieces = Vec::new();
+    let mut printed = 0;
+    for span in spans {
+        pieces.push(Span::raw(&text[printed..span.start]));
+        pieces.push(Span::styled(&text[span.clone()], style));
+        printed = span.end;
+    }
+    pieces.push(Span::raw(&text[printed..]));
+    pieces
+}
+
+fn editor(path: &Path, line: usize) -> Command {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    editor_command(&editor, path, line)
+}
+
+/// Builds the command to open `path` at `line` with `editor`, which may
+/// include arguments of its own, such as `code --wait`. Nearly every
+/// terminal editor understands `+LINE`.
+fn editor_command(editor: &str, path: &Path, line: usize) -> Command {
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("vi"));
+    command.args(words).arg(format!("+{}", line)).arg(path);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::config;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn documents() -> Vec<Document> {
+        let document = |name: &str, text: &str| Document {
+            path: PathBuf::from(name),
+            name: name.to_string(),
+            lines: text.lines().map(String::from).collect(),
+        };
+        vec![
+            document(
+                "poem.txt",
+                "I'm nobody! Who are you?\nAre you nobody, too?\nHow dreary to be somebody!",
+            ),
+            document("lib.rs", "fn body() {}\nfn main() {}"),
+        ]
+    }
+
+    fn typed(app: &mut App, text: &str) {
+        for c in text.chars() {
+            app.key(KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    fn found(app: &App) -> Vec<(usize, usize)> {
+        app.hits
+            .iter()
+            .map(|hit| (hit.document, hit.line))
+            .collect()
+    }
+
+    #[test]
+    fn editing_the_query_reruns_the_search() {
+        let config = config(&["body"]);
+        let mut app = App::new(&config, documents());
+        assert_eq!(vec![(0, 0), (0, 1), (0, 2), (1, 0)], found(&app));
+
+        typed(&mut app, "!");
+        assert_eq!(vec![(0, 0), (0, 2)], found(&app));
+        assert_eq!(Some(&(6..11)), app.hits[0].spans.first());
+
+        app.key(KeyEvent::from(KeyCode::Backspace));
+        app.key(KeyEvent::from(KeyCode::Backspace));
+        typed(&mut app, "y(");
+        assert!(app.error.is_some());
+        assert_eq!(vec![(0, 0), (0, 1), (0, 2), (1, 0)], found(&app));
+
+        app.key(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL));
+        assert!(app.hits.is_empty() && app.error.is_none());
+    }
+
+    #[test]
+    fn unreadable_files_are_skipped() {
+        let config = config(&["package"]);
+        let files = [PathBuf::from("Cargo.toml"), PathBuf::from("missing.txt")];
+        let app = App::load(&config, &files);
+
+        assert_eq!(1, app.documents.len());
+        assert_eq!(vec![(0, 0)], found(&app));
+        assert!(app
+            .error
+            .unwrap()
+            .starts_with("Couldn't read missing.txt: "));
+    }
+
+    #[test]
+    fn only_literal_extensions_narrow_the_search() {
+        let config = config(&["body"]);
+        let mut app = App::new(&config, documents());
+
+        app.query.push_str(" {");
+        assert!(!app.narrows());
+        app.query = String::from("nobody");
+        assert!(app.narrows());
+        app.query = String::from("nobod.");
+        assert!(!app.narrows());
+    }
+
+    #[test]
+    fn selection_moves_and_opens_the_editor() {
+        let config = config(&["body"]);
+        let mut app = App::new(&config, documents());
+
+        app.key(KeyEvent::from(KeyCode::Up));
+        assert_eq!(Some(0), app.list.selected());
+        app.key(KeyEvent::from(KeyCode::PageDown));
+        assert_eq!(Some(3), app.list.selected());
+        assert_eq!(Action::Edit(1, 1), app.key(KeyEvent::from(KeyCode::Enter)));
+        assert_eq!(Action::Quit, app.key(KeyEvent::from(KeyCode::Esc)));
+    }
+
+    #[test]
+    fn draws_results_and_preview() {
+        let config = config(&["dreary"]);
+        let mut app = App::new(&config, documents());
+        let mut terminal = Terminal::new(TestBackend::new(80, 8)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("> dreary"));
+        assert!(screen.contains("1 matches"));
+        assert!(screen.contains("poem.txt:3:How dreary"));
+        assert!(screen.contains("2 Are you nobody, too?"));
+    }
+
+    #[test]
+    fn editor_gets_the_line_number() {
+        let command = editor_command("code --wait", Path::new("src/lib.rs"), 12);
+
+        assert_eq!("code", command.get_program());
+        assert_eq!(
+            vec!["--wait", "+12", "src/lib.rs"],
+            command.get_args().collect::<Vec<_>>()
+        );
+    }
+}
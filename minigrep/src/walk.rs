@@ -0,0 +1,208 @@
+use ignore::overrides::OverrideBuilder;
+use ignore::types::TypesBuilder;
+use ignore::WalkBuilder;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Config;
+
+/// Expands the given paths into the files beneath them, descending into
+/// directories recursively. Entries are visited in sorted order so output is
+/// stable between runs, and symbolic links found while walking a directory
+/// are not followed.
+///
+/// Unless `config` says otherwise, files matched by `.gitignore`, `.ignore`
+/// and similar files are skipped, as are hidden files and directories. The
+/// `--glob` and `--type` filters also only apply to files found by walking a
+/// directory; paths given explicitly are always searched.
+///
+/// A path that doesn't exist or can't be read is reported on stderr and
+/// doesn't stop the walk; along with the files, returns how many there were.
+/// Only invalid `--glob` and `--type` filters are returned as errors.
+pub fn files(paths: &[PathBuf], config: &Config) -> io::Result<(Vec<PathBuf>, usize)> {
+    walk(paths, None, config)
+}
+
+/// Like `files`, but only returns the files at or beneath `within`, which is
+/// named the way `files` would name it. Only the directories leading there
+/// are read, and the same filters apply as if all of `paths` were walked.
+pub fn files_within(
+    paths: &[PathBuf],
+    within: &Path,
+    config: &Config,
+) -> io::Result<(Vec<PathBuf>, usize)> {
+    walk(paths, Some(within), config)
+}
+
+fn walk(
+    paths: &[PathBuf],
+    within: Option<&Path>,
+    config: &Config,
+) -> io::Result<(Vec<PathBuf>, usize)> {
+    let mut files = Vec::new();
+    let mut failed = 0;
+    for path in paths {
+        if within.is_some_and(|within| !within.starts_with(path) && !path.starts_with(within)) {
+            continue;
+        }
+        if path == Path::new("-") {
+            files.push(path.clone());
+            continue;
+        }
+        if let Err(err) = fs::metadata(path) {
+            eprintln!("minigrep: {}: {}", path.display(), err);
+            failed += 1;
+            continue;
+        }
+
+        let mut overrides = OverrideBuilder::new(path);
+        for glob in &config.globs {
+            overrides.add(glob).map_err(to_io_error)?;
+        }
+
+        let mut types = TypesBuilder::new();
+        types.add_defaults();
+        for name in &config.types {
+            types.select(name);
+        }
+        for name in &config.types_not {
+            types.negate(name);
+        }
+
+        // A `--type` match would otherwise let hidden files through.
+        let hidden = config.hidden;
+        let within = within.map(Path::to_path_buf);
+        let walker = WalkBuilder::new(path)
+            .hidden(!hidden)
+            .filter_entry(move |entry| {
+                let path = entry.path();
+                (hidden || entry.depth() == 0 || !is_hidden(entry.file_name()))
+                    && within
+                        .as_ref()
+                        .is_none_or(|within| within.starts_with(path) || path.starts_with(within))
+            })
+            .ignore(!config.no_ignore)
+            .git_ignore(!config.no_ignore)
+            .git_global(!config.no_ignore)
+            .git_exclude(!config.no_ignore)
+            .parents(!config.no_ignore)
+            .require_git(false)
+            .overrides(overrides.build().map_err(to_io_error)?)
+            .types(types.build().map_err(to_io_error)?)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+
+        for entry in walker {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    eprintln!("minigrep: {}", err);
+                    failed += 1;
+                    continue;
+                }
+            };
+            if entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_file())
+            {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    Ok((files, failed))
+}
+
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+fn to_io_error(err: ignore::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+/// Uses the same heuristic as grep: anything containing a NUL byte is binary.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents.contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn nul_byte_means_binary() {
+        assert!(is_binary(b"\x7fELF\x02\x01\x01\x00"));
+        assert!(!is_binary("Rust:\nsafe, fast, productive.".as_bytes()));
+    }
+
+    #[test]
+    fn honours_ignore_files_and_filters() {
+        let root = TempDir::new("walk");
+        let paths = [root.to_path_buf()];
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        for file in &["main.rs", "notes.txt", ".secret.rs", "target/build.rs"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        let names = |config: &Config| {
+            let mut names: Vec<_> = files(&paths, config)
+                .unwrap()
+                .0
+                .iter()
+                .map(|file| file.strip_prefix(&root).unwrap().display().to_string())
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(vec!["main.rs", "notes.txt"], names(&Config::default()));
+
+        let config = Config {
+            hidden: true,
+            no_ignore: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            vec![
+                ".gitignore",
+                ".secret.rs",
+                "main.rs",
+                "notes.txt",
+                "target/build.rs"
+            ],
+            names(&config)
+        );
+
+        let config = Config {
+            types: vec![String::from("rust")],
+            ..Config::default()
+        };
+        assert_eq!(vec!["main.rs"], names(&config));
+
+        let config = Config {
+            globs: vec![String::from("!*.rs")],
+            ..Config::default()
+        };
+        assert_eq!(vec!["notes.txt"], names(&config));
+
+        let config = Config::default();
+        let within = |path: &str| files_within(&paths, &root.join(path), &config).unwrap().0;
+        assert_eq!(vec![root.join("main.rs")], within("main.rs"));
+        assert!(within("target/build.rs").is_empty());
+        assert!(within(".secret.rs").is_empty());
+    }
+
+    #[test]
+    fn missing_paths_do_not_stop_the_walk() {
+        let root = TempDir::new("walk-missing");
+        fs::write(root.join("a.txt"), "").unwrap();
+        let paths = [root.join("a.txt"), root.join("nope.txt"), root.join("b")];
+
+        let (files, failed) = files(&paths, &Config::default()).unwrap();
+        assert_eq!(vec![root.join("a.txt")], files);
+        assert_eq!(2, failed);
+    }
+}
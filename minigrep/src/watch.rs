@@ -0,0 +1,374 @@
+//! `--watch`: keep running and print the lines that match as they are
+//! appended to the searched files, like `tail -f | grep` over a directory.
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use crate::printer::Printer;
+use crate::{searcher, walk, Config, Match, Sink, Stats};
+
+/// How long to wait for more events after one arrives, so that a burst of
+/// writes is read in one go.
+const SETTLE: Duration = Duration::from_millis(50);
+
+/// How far into a file has been searched.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Tail {
+    /// Byte offset of the first line not yet searched.
+    offset: u64,
+    /// Number of lines before `offset`.
+    lines: usize,
+}
+
+/// Watches `paths` until interrupted, searching whatever is written to the
+/// files beneath them. Files that exist at the start are only searched from
+/// their current end; files created later are searched from the beginning.
+pub fn run(
+    config: &Config,
+    paths: &[PathBuf],
+    with_filename: bool,
+) -> Result<bool, Box<dyn Error>> {
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    // Watching canonical paths makes the watcher's names predictable, so
+    // that they can be turned back into the ones the walk uses.
+    let mut roots = Vec::new();
+    for path in paths {
+        let root = path.canonicalize()?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+        roots.push((root, path.clone()));
+    }
+
+    let mut tails = HashMap::new();
+    for file in walk::files(paths, config)?.0 {
+        match skip_to_end(&file) {
+            Ok(tail) => {
+                tails.insert(file, tail);
+            }
+            Err(err) => eprintln!("minigrep: {}: {}", file.display(), err),
+        }
+    }
+
+    let stdout = io::stdout();
+    loop {
+        let mut changes = Changes::default();
+        let mut event = events.recv()?;
+        loop {
+            // Errors from the watcher are reported but don't stop the watch.
+            match event {
+                Ok(event) => changes.note(&roots, event, &mut tails),
+                Err(err) => eprintln!("minigrep: {}", err),
+            }
+            match events.recv_timeout(SETTLE) {
+                Ok(next) => event = next,
+                Err(_) => break,
+            }
+        }
+        for name in &changes.removed {
+            tails.retain(|file, _| !file.starts_with(name));
+        }
+        changes.changed.sort();
+        changes.changed.dedup();
+
+        // Files seen before only need a size check to find out whether they
+        // grew. Anything new is looked for with the usual filters.
+        let mut out = stdout.lock();
+        for path in changes.changed {
+            let files = if tails.contains_key(&path) {
+                vec![path]
+            } else {
+                match walk::files_within(paths, &path, config) {
+                    Ok((files, _)) => files,
+                    Err(err) => {
+                        eprintln!("minigrep: {}", err);
+                        continue;
+                    }
+                }
+            };
+            for file in files {
+                let moved = changes.moved.remove(&file);
+                let tail = tails
+                    .entry(file.clone())
+                    .or_insert(moved.unwrap_or_default());
+                let name = file.display().to_string();
+                if let Err(err) = search_new(config, &file, &name, with_filename, tail, &mut out) {
+                    eprintln!("minigrep: {}: {}", name, err);
+                }
+            }
+        }
+        out.flush()?;
+    }
+}
+
+/// What a burst of events touched, with paths named as the walk names them.
+#[derive(Debug, Default)]
+struct Changes {
+    /// Files, and directories new to the watch, to search.
+    changed: Vec<PathBuf>,
+    /// Where renamed files went, with how far they had been searched, so
+    /// that only lines written to them afterwards are searched.
+    moved: HashMap<PathBuf, Tail>,
+    /// Files and directories that are gone, to be forgotten once the burst
+    /// is over; a rename reports its old name as gone before saying where
+    /// the file went.
+    removed: Vec<PathBuf>,
+}
+
+impl Changes {
+    fn note(
+        &mut self,
+        roots: &[(PathBuf, PathBuf)],
+        event: Event,
+        tails: &mut HashMap<PathBuf, Tail>,
+    ) {
+        let paths: Vec<(&PathBuf, PathBuf)> = event
+            .paths
+            .iter()
+            .filter_map(|path| Some((path, relative(roots, path)?)))
+            .collect();
+        if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [(_, from), (_, to)]) =
+            (event.kind, &paths[..])
+        {
+            if let Some(tail) = tails.remove(from) {
+                self.moved.insert(to.clone(), tail);
+            }
+        }
+        // A directory only needs walking when it is new here; changes to the
+        // files in it have events of their own.
+        let added = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_))
+        );
+        for (path, name) in paths {
+            if path.is_dir() {
+                if added {
+                    self.changed.push(name);
+                }
+            } else if path.exists() {
+                self.changed.push(name);
+            } else {
+                self.removed.push(name);
+            }
+        }
+    }
+}
+
+/// Turns `path`, as reported by the watcher, back into the name the walk
+/// gives it: the path it was found under, as given, joined with the rest.
+fn relative(roots: &[(PathBuf, PathBuf)], path: &Path) -> Option<PathBuf> {
+    roots.iter().find_map(|(root, given)| {
+        let rest = path.strip_prefix(root).ok()?;
+        Some(if rest.as_os_str().is_empty() {
+            given.clone()
+        } else {
+            given.join(rest)
+        })
+    })
+}
+
+/// Returns the position just after the last complete line of `file`,
+/// reading it a block at a time so that large logs aren't held in memory.
+fn skip_to_end(file: &Path) -> io::Result<Tail> {
+    let mut reader = File::open(file)?;
+    let mut buffer = [0; 64 * 1024];
+    let mut tail = Tail::default();
+    let mut read_before = 0;
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(tail),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        let block = &buffer[..read];
+        if let Some(i) = memchr::memrchr(b'\n', block) {
+            tail.offset = read_before + i as u64 + 1;
+            tail.lines += memchr::memchr_iter(b'\n', block).count();
+        }
+        read_before += read as u64;
+    }
+}
+
+/// Searches the complete lines added to `file` since `tail`, and moves
+/// `tail` past them. A line still being written is left for next time, and
+/// a file that shrank is assumed to have been truncated and is searched
+/// again from the start.
+fn search_new<W: Write>(
+    config: &Config,
+    file: &Path,
+    name: &str,
+    with_filename: bool,
+    tail: &mut Tail,
+    out: W,
+) -> io::Result<()> {
+    let mut reader = File::open(file)?;
+    let len = reader.metadata()?.len();
+    if len < tail.offset {
+        *tail = Tail::default();
+    }
+    if len == tail.offset {
+        return Ok(());
+    }
+    reader.seek(SeekFrom::Start(tail.offset))?;
+    let mut added = Vec::new();
+    reader.read_to_end(&mut added)?;
+    let complete = match memchr::memrchr(b'\n', &added) {
+        Some(i) => &added[..i + 1],
+        None => return Ok(()),
+    };
+    let start = *tail;
+    tail.offset += complete.len() as u64;
+    tail.lines += memchr::memchr_iter(b'\n', complete).count();
+
+    if walk::is_binary(complete) {
+        return Ok(());
+    }
+    let mut printer = Printer::new(config, name, with_filename, out);
+    let mut sink = Shifted {
+        sink: &mut printer,
+        start,
+    };
+    // `--max-count` applies to a whole file, which a watch never finishes.
+    searcher(config)
+        .max_count(None)
+        .build()
+        .search(&config.matcher, complete, &mut sink)?;
+    Ok(())
+}
+
+/// Passes lines on to `sink` numbered as if the search had started at the
+/// beginning of the file rather than at `start`.
+struct Shifted<'s, S> {
+    sink: &'s mut S,
+    start: Tail,
+}
+
+impl<S> Shifted<'_, S> {
+    fn shift<'a>(&self, found: &Match<'a>) -> Match<'a> {
+        Match {
+            line_number: self.start.lines + found.line_number,
+            byte_offset: self.start.offset as usize + found.byte_offset,
+            spans: found.spans.clone(),
+            line: found.line,
+        }
+    }
+}
+
+impl<S: Sink> Sink for Shifted<'_, S> {
+    fn matched(&mut self, found: &Match) -> io::Result<bool> {
+        let found = self.shift(found);
+        self.sink.matched(&found)
+    }
+
+    fn context(&mut self, line: &Match) -> io::Result<bool> {
+        let line = self.shift(line);
+        self.sink.context(&line)
+    }
+
+    fn finish(&mut self, stats: &Stats) -> io::Result<()> {
+        self.sink.finish(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{config, TempDir};
+    use notify::event::RemoveKind;
+    use std::fs::{self, OpenOptions};
+
+    fn append(file: &Path, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(file).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn prints_only_complete_new_lines() {
+        let dir = TempDir::new("watch");
+        let log = dir.join("app.log");
+        fs::write(&log, "ERROR old\nok\n").unwrap();
+        let config = config(&["-n", "-b", "ERROR"]);
+        let search = |tail: &mut Tail| {
+            let mut out = Vec::new();
+            search_new(&config, &log, "app.log", false, tail, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let mut tail = skip_to_end(&log).unwrap();
+        assert_eq!(
+            Tail {
+                offset: 13,
+                lines: 2
+            },
+            tail
+        );
+
+        append(&log, "ERROR new\nfine\nERROR part");
+        assert_eq!("3:13:ERROR new\n", search(&mut tail));
+        append(&log, "ial\n");
+        assert_eq!("5:28:ERROR partial\n", search(&mut tail));
+        assert_eq!("", search(&mut tail));
+
+        fs::write(&log, "ERROR rotated\n").unwrap();
+        assert_eq!("1:0:ERROR rotated\n", search(&mut tail));
+    }
+
+    #[test]
+    fn follows_renamed_files_and_forgets_removed_ones() {
+        let dir = TempDir::new("watch-events");
+        let roots = [(dir.canonicalize().unwrap(), PathBuf::from("logs"))];
+        fs::write(dir.join("app.log.1"), "").unwrap();
+        let tail = Tail {
+            offset: 10,
+            lines: 1,
+        };
+        let mut tails = HashMap::new();
+        tails.insert(PathBuf::from("logs/app.log"), tail);
+        tails.insert(PathBuf::from("logs/old.log"), tail);
+        let mut changes = Changes::default();
+
+        // The old name is reported as gone first, then both names together.
+        let root = &roots[0].0;
+        let from = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::From)))
+            .add_path(root.join("app.log"));
+        changes.note(&roots, from, &mut tails);
+        let renamed = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(root.join("app.log"))
+            .add_path(root.join("app.log.1"));
+        changes.note(&roots, renamed, &mut tails);
+        let removed =
+            Event::new(EventKind::Remove(RemoveKind::File)).add_path(root.join("old.log"));
+        changes.note(&roots, removed, &mut tails);
+
+        assert_eq!(vec![PathBuf::from("logs/app.log.1")], changes.changed);
+        assert_eq!(Some(&tail), changes.moved.get(Path::new("logs/app.log.1")));
+        assert!(changes.removed.contains(&PathBuf::from("logs/old.log")));
+        assert_eq!(
+            vec![Path::new("logs/old.log")],
+            tails.keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn skips_to_the_end_of_large_files() {
+        let dir = TempDir::new("watch-large");
+        let log = dir.join("big.log");
+        let line = "x".repeat(99) + "\n";
+        fs::write(&log, line.repeat(2000) + "partial").unwrap();
+
+        assert_eq!(
+            Tail {
+                offset: 200_000,
+                lines: 2000
+            },
+            skip_to_end(&log).unwrap()
+        );
+    }
+}